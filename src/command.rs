@@ -0,0 +1,299 @@
+use crate::Document;
+use crate::Position;
+use crate::SearchDirection;
+use std::cmp;
+use std::fmt;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// An edit or a move that works the same whether it comes from a key in the editor or from a
+/// line in a script.
+#[derive(Clone)]
+pub enum Command {
+    /// Moves the cursor, staying inside the document.
+    Goto(Position),
+    /// Moves the cursor to the next match from it on, or the closest one before it going backward.
+    Search(String, SearchDirection),
+    /// Replaces the next match from the cursor on and leaves the cursor after the replacement.
+    Replace(String, String),
+    /// Replaces every match in the document and leaves the cursor after the last one.
+    ReplaceAll(String, String),
+    /// Inserts text at the cursor and leaves the cursor after it.
+    Insert(String),
+    Save,
+}
+
+impl Command {
+    /// Reads one line of a script. The commands are
+    ///
+    /// ```text
+    /// goto <line> [<column>]
+    /// search <text>
+    /// replace <text> [<replacement>]
+    /// replace-all <text> [<replacement>]
+    /// insert <text>
+    /// save
+    /// ```
+    ///
+    /// Lines and columns count from 1. `search` and `insert` take the rest of the line, while the
+    /// two arguments of `replace` are split at white space. Without a replacement, the text is
+    /// deleted. In all of them `\n` is a line break, `\t` a tab, `\s` a space and `\\` a backslash.
+    ///
+    /// # Errors
+    ///
+    /// If the line isn't one of the commands above, with a message saying why.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim_start();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match name {
+            "goto" => {
+                let mut numbers = rest.split_whitespace().map(|number| {
+                    number.parse::<usize>().ok().filter(|n| *n > 0).ok_or(format!("not a line or column: {number}"))
+                });
+                let y = numbers.next().ok_or("goto needs a line")??;
+                let x = numbers.next().unwrap_or(Ok(1))?;
+                if numbers.next().is_some() {
+                    return Err("goto takes a line and a column".to_string());
+                }
+                Ok(Self::Goto(Position {
+                    x: x.saturating_sub(1),
+                    y: y.saturating_sub(1),
+                }))
+            },
+            "search" => Ok(Self::Search(query(rest)?, SearchDirection::Forward)),
+            "replace" | "replace-all" => {
+                let mut arguments = rest.split_whitespace();
+                let (Some(text), replacement, None) = (arguments.next(), arguments.next().unwrap_or_default(), arguments.next()) else {
+                    return Err(format!("{name} takes a text and its replacement"));
+                };
+                let text = query(text)?;
+                let replacement = unescape(replacement)?;
+                if name == "replace" {
+                    Ok(Self::Replace(text, replacement))
+                } else {
+                    Ok(Self::ReplaceAll(text, replacement))
+                }
+            },
+            "insert" => Ok(Self::Insert(unescape(rest)?)),
+            "save" if rest.trim().is_empty() => Ok(Self::Save),
+            "save" => Err("save takes no arguments".to_string()),
+            _ => Err(format!("unknown command: {name}")),
+        }
+    }
+
+    /// Carries the command out on `document`, with `cursor` as the cursor.
+    ///
+    /// # Errors
+    ///
+    /// If there was nothing to find, or the document couldn't be saved, with a message saying so.
+    pub fn run(&self, document: &mut Document, cursor: &mut Position) -> Result<(), String> {
+        match self {
+            Self::Goto(position) => {
+                let y = cmp::min(position.y, document.len().saturating_sub(1));
+                let width = document.get_row(y).map_or(0, crate::Row::len);
+                *cursor = Position {
+                    x: cmp::min(position.x, width),
+                    y,
+                };
+            },
+            Self::Search(text, direction) => {
+                *cursor = document.find(text, cursor, *direction).ok_or(format!("not found: {text}"))?;
+            },
+            Self::Replace(text, replacement) => {
+                let found = document.find(text, cursor, SearchDirection::Forward).ok_or(format!("not found: {text}"))?;
+                *cursor = replace(document, &found, text, replacement);
+            },
+            Self::ReplaceAll(text, replacement) => {
+                let mut at = Position::default();
+                let mut replaced = false;
+                while let Some(found) = document.find(text, &at, SearchDirection::Forward) {
+                    at = replace(document, &found, text, replacement);
+                    replaced = true;
+                }
+                if !replaced {
+                    return Err(format!("not found: {text}"));
+                }
+                *cursor = at;
+            },
+            Self::Insert(text) => *cursor = document.insert_str(cursor, text),
+            Self::Save => {
+                let Some(file_name) = document.file_name.clone() else {
+                    return Err("no file name to save to".to_string());
+                };
+                document.save().map_err(|error| format!("can't save {file_name}: {error}"))?;
+                // trailing white space may have been trimmed from under the cursor
+                let width = document.get_row(cursor.y).map_or(0, crate::Row::len);
+                cursor.x = cmp::min(cursor.x, width);
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Why a script stopped. Lines count from 1.
+#[derive(Debug)]
+pub enum ScriptError {
+    /// A line that isn't a command. Nothing was run, since the whole script is read first.
+    Invalid(usize, String),
+    /// A command that couldn't be carried out, such as a search without a match.
+    Failed(usize, String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(line, message) | Self::Failed(line, message) => write!(formatter, "line {line}: {message}"),
+        }
+    }
+}
+
+/// Runs the commands of `script` on `document` one after the other, starting with the cursor at
+/// the top. Blank lines and lines starting with `#` are skipped. A `search` right after another
+/// one goes on to the next match. Nothing is saved unless the script says so.
+///
+/// # Errors
+///
+/// If a line isn't a command, or at the first command that fails.
+pub fn run_script(script: &str, document: &mut Document) -> Result<(), ScriptError> {
+    let mut commands = Vec::new();
+    for (index, line) in script.lines().enumerate() {
+        let number = index.saturating_add(1);
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let command = Command::parse(line).map_err(|message| ScriptError::Invalid(number, message))?;
+        commands.push((number, command));
+    }
+
+    let mut cursor = Position::default();
+    // the cursor is on the match the last command found, which searching again has to get past
+    let mut on_match = false;
+    for (number, command) in commands {
+        if on_match && matches!(command, Command::Search(_, SearchDirection::Forward)) {
+            cursor = next_position(document, &cursor);
+        }
+        command.run(document, &mut cursor).map_err(|message| ScriptError::Failed(number, message))?;
+        on_match = matches!(command, Command::Search(..));
+    }
+    Ok(())
+}
+
+// Searches look at one row at a time, so the text can't span lines.
+fn query(text: &str) -> Result<String, String> {
+    let text = unescape(text)?;
+    if text.is_empty() {
+        return Err("nothing to search for".to_string());
+    }
+    if text.contains('\n') {
+        return Err("can't search across lines".to_string());
+    }
+    Ok(text)
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('s') => result.push(' '),
+            Some('\\') => result.push('\\'),
+            Some(other) => return Err(format!("unknown escape: \\{other}")),
+            None => return Err("a \\ at the end of the line".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+// The grapheme after `at`, which is the start of the next row at the end of one.
+fn next_position(document: &Document, at: &Position) -> Position {
+    match document.get_row(at.y) {
+        Some(row) if at.x < row.len() => Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        },
+        _ => Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        },
+    }
+}
+
+// Returns where the replacement ends.
+fn replace(document: &mut Document, at: &Position, text: &str, replacement: &str) -> Position {
+    for _ in text.graphemes(true) {
+        document.delete(at);
+    }
+    document.insert_str(at, replacement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Command {
+        Command::parse(line).unwrap()
+    }
+
+    fn run(text: &str, script: &str) -> Result<String, ScriptError> {
+        let mut document = Document::from(text);
+        run_script(script, &mut document)?;
+        Ok(String::from_utf8(document.contents()).unwrap())
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape(r"a\nb\tc\sd\\e").unwrap(), "a\nb\tc d\\e");
+        assert_eq!(unescape("plain").unwrap(), "plain");
+        assert_eq!(unescape(r"a\x").unwrap_err(), r"unknown escape: \x");
+        assert_eq!(unescape("a\\").unwrap_err(), "a \\ at the end of the line");
+    }
+
+    #[test]
+    fn parsing() {
+        assert!(matches!(parse("goto 3"), Command::Goto(Position { x: 0, y: 2 })));
+        assert!(matches!(parse("  goto 3 7"), Command::Goto(Position { x: 6, y: 2 })));
+        assert!(matches!(parse(r"search fn main\s"), Command::Search(text, SearchDirection::Forward) if text == "fn main "));
+        assert!(matches!(parse(r"replace a\s b\n"), Command::Replace(text, replacement) if text == "a " && replacement == "b\n"));
+        assert!(matches!(parse("replace-all a b"), Command::ReplaceAll(text, replacement) if text == "a" && replacement == "b"));
+        assert!(matches!(parse("replace a"), Command::Replace(text, replacement) if text == "a" && replacement.is_empty()));
+        assert!(matches!(parse(r"insert \t x"), Command::Insert(text) if text == "\t x"));
+        assert!(matches!(parse("save"), Command::Save));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |line: &str| Command::parse(line).err().unwrap();
+        assert_eq!(error("goto"), "goto needs a line");
+        assert_eq!(error("goto 0"), "not a line or column: 0");
+        assert_eq!(error("goto 1 2 3"), "goto takes a line and a column");
+        assert_eq!(error("search"), "nothing to search for");
+        assert_eq!(error(r"search a\nb"), "can't search across lines");
+        assert_eq!(error("replace"), "replace takes a text and its replacement");
+        assert_eq!(error("replace a b c"), "replace takes a text and its replacement");
+        assert_eq!(error("save now"), "save takes no arguments");
+        assert_eq!(error("jump 3"), "unknown command: jump");
+    }
+
+    #[test]
+    fn searching_again_goes_on_to_the_next_match() {
+        let script = "search foo\nsearch foo\nsearch foo\ninsert [";
+        assert_eq!(run("x foo\nfoo foo", script).unwrap(), "x foo\nfoo [foo\n");
+        assert!(matches!(run("x foo", "search foo\nsearch foo"), Err(ScriptError::Failed(2, _))));
+        // the first search finds a match right at the cursor
+        assert_eq!(run("foo bar", "search foo\ninsert [").unwrap(), "[foo bar\n");
+        assert_eq!(run("foo foo", "search foo\nsearch foo\ninsert [").unwrap(), "foo [foo\n");
+        assert_eq!(run("a\nfoo", "goto 2\nsearch foo\ninsert [").unwrap(), "a\n[foo\n");
+        assert_eq!(run("foo foo", "search foo\ngoto 1\nsearch foo\ninsert [").unwrap(), "[foo foo\n");
+    }
+
+    #[test]
+    fn a_missing_replacement_deletes() {
+        assert_eq!(run("a, b, c", "replace-all ,").unwrap(), "a b c\n");
+        assert_eq!(run("a, b, c", "goto 1 3\nreplace ,").unwrap(), "a, b c\n");
+    }
+}
@@ -0,0 +1,379 @@
+use crate::Editor;
+use crate::Theme;
+use crate::Wrap;
+use crate::keymap::{self, Keymap};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+use toml::{Table, Value};
+
+const QUIT_TIMES: u8 = 3;
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TAB_WIDTH: i64 = 32;
+
+/// The settings that can be different for each file type.
+#[derive(Clone, Copy)]
+pub struct FileSettings {
+    pub tab_width: Option<usize>, // if not set, the file type decides
+    pub line_numbers: bool,
+    pub wrap: Wrap,
+    pub autosave: Option<Duration>, // after how long without typing, if at all
+    pub rainbow_brackets: bool,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            tab_width: None,
+            line_numbers: false,
+            wrap: Wrap::Off,
+            autosave: None,
+            rainbow_brackets: false,
+        }
+    }
+}
+
+// Only what one section of the file sets, to be laid over the settings from the sections before it.
+#[derive(Default)]
+struct Overrides {
+    tab_width: Option<usize>,
+    line_numbers: Option<bool>,
+    wrap: Option<Wrap>,
+    autosave: Option<Duration>, // zero turns it off
+    rainbow_brackets: Option<bool>,
+}
+
+impl Overrides {
+    fn apply(&self, settings: &mut FileSettings) {
+        settings.tab_width = self.tab_width.or(settings.tab_width);
+        settings.line_numbers = self.line_numbers.unwrap_or(settings.line_numbers);
+        settings.wrap = self.wrap.unwrap_or(settings.wrap);
+        if let Some(autosave) = self.autosave {
+            settings.autosave = Some(autosave).filter(|autosave| !autosave.is_zero());
+        }
+        settings.rainbow_brackets = self.rainbow_brackets.unwrap_or(settings.rainbow_brackets);
+    }
+}
+
+/// The settings from `~/.config/hecto/config.toml`. For example:
+///
+/// ```toml
+/// theme = "gruvbox-dark"
+/// quit_times = 1
+/// message_timeout = 10   # seconds
+/// line_numbers = true
+/// wrap = "words"         # or "off" and "on"
+/// autosave = 30          # seconds without typing, 0 to turn it off
+///
+/// [keys]
+/// ctrl-o = "save"
+/// ctrl-s = "none"
+/// "ctrl-k ctrl-w" = "wrap"
+///
+/// [filetype.rust]
+/// tab_width = 4
+/// rainbow_brackets = true
+/// ```
+///
+/// `tab_width`, `line_numbers`, `wrap`, `autosave` and `rainbow_brackets` can be set for all files
+/// and for each file type. `[keys]` binds key sequences to the commands from
+/// [`Editor::commands`], or unbinds them with `"none"`. Entries that don't make sense are left
+/// out and reported in [`Config::errors`], the rest still applies.
+pub struct Config {
+    theme: Option<String>,
+    quit_times: u8,
+    message_timeout: Duration,
+    keymap: Keymap,
+    general: Overrides,
+    file_types: HashMap<String, Overrides>, // by the file type's name in lower case
+    errors: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            quit_times: QUIT_TIMES,
+            message_timeout: MESSAGE_TIMEOUT,
+            keymap: Keymap::default(),
+            general: Overrides::default(),
+            file_types: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file from `$XDG_CONFIG_HOME/hecto`, or `~/.config/hecto` if that
+    /// isn't set. Without one, everything stays at its default.
+    pub fn load() -> Self {
+        let Some(path) = config_path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(error) if error.kind() == ErrorKind::NotFound => Self::default(),
+            Err(error) => Self {
+                errors: vec![format!("can't read {}: {error}", path.display())],
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Reads the settings from the text of a configuration file.
+    pub fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        let table = match text.parse::<Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                // the message has to fit on the message bar
+                let message = error.message().replace('\n', ", ");
+                let place = error.span().map(|span| {
+                    let line = text.get(..span.start).unwrap_or_default().matches('\n').count();
+                    format!(" on line {}", line.saturating_add(1))
+                });
+                config.errors.push(format!("{message}{}", place.unwrap_or_default()));
+                return config;
+            },
+        };
+
+        for (name, value) in &table {
+            match name.as_str() {
+                "theme" => match value.as_str() {
+                    Some(theme) if Theme::by_name(theme).is_some() => config.theme = Some(theme.to_string()),
+                    _ => {
+                        let themes: Vec<String> = Theme::builtin().iter().map(|theme| format!("\"{}\"", theme.name())).collect();
+                        config.errors.push(expected(name, &format!("one of {}", themes.join(", ")), value));
+                    },
+                },
+                "quit_times" => match number(value, 0, i64::from(u8::MAX)) {
+                    Some(times) => config.quit_times = u8::try_from(times).unwrap_or(QUIT_TIMES),
+                    None => config.errors.push(expected(name, "a number from 0 to 255", value)),
+                },
+                "message_timeout" => match number(value, 1, i64::MAX) {
+                    Some(seconds) => config.message_timeout = Duration::from_secs(seconds.unsigned_abs()),
+                    None => config.errors.push(expected(name, "a number of seconds", value)),
+                },
+                "keys" => {
+                    if let Some(keys) = value.as_table() {
+                        config.read_keys(keys);
+                    } else {
+                        config.errors.push(expected(name, "a table", value));
+                    }
+                },
+                "filetype" => {
+                    let Some(file_types) = value.as_table() else {
+                        config.errors.push(expected(name, "a table", value));
+                        continue;
+                    };
+                    for (file_type, value) in file_types {
+                        let section = format!("filetype.{file_type}");
+                        if let Some(table) = value.as_table() {
+                            let overrides = read_overrides(table, &format!("{section}."), &mut config.errors);
+                            config.file_types.insert(file_type.to_lowercase(), overrides);
+                        } else {
+                            config.errors.push(expected(&section, "a table", value));
+                        }
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        let general: Table = table.into_iter().filter(|(name, _)| {
+            !matches!(name.as_str(), "theme" | "quit_times" | "message_timeout" | "keys" | "filetype")
+        }).collect();
+        config.general = read_overrides(&general, "", &mut config.errors);
+        config
+    }
+
+    fn read_keys(&mut self, keys: &Table) {
+        for (key_names, value) in keys {
+            let Some(keys) = keymap::parse_keys(key_names) else {
+                self.errors.push(format!("keys: {key_names:?} isn't a key, try something like \"ctrl-s\", \"alt-x\", \"f5\" or \"ctrl-k ctrl-c\""));
+                continue;
+            };
+            match value.as_str() {
+                Some("none") => self.keymap.unbind(&keys),
+                Some(name) if Editor::commands().iter().any(|command| command.name() == name) => self.keymap.bind(keys, name),
+                _ => self.errors.push(expected(&format!("keys.{key_names}"), "\"none\" or the name of a command", value)),
+            }
+        }
+    }
+
+    /// What was wrong with the file, one message for each entry that was left out.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// The name of the theme to start with, if there is one.
+    pub fn theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    /// How many more times Ctrl-Q has to be pressed to quit without saving.
+    pub fn quit_times(&self) -> u8 {
+        self.quit_times
+    }
+
+    /// How long a message stays in the message bar.
+    pub fn message_timeout(&self) -> Duration {
+        self.message_timeout
+    }
+
+    /// The default key bindings with the ones from the file laid over them.
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// The general settings with those for `file_type` laid over them.
+    pub fn for_file_type(&self, file_type: &str) -> FileSettings {
+        let mut settings = FileSettings::default();
+        self.general.apply(&mut settings);
+        if let Some(overrides) = self.file_types.get(&file_type.to_lowercase()) {
+            overrides.apply(&mut settings);
+        }
+        settings
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let directory = env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(directory.join("hecto").join("config.toml"))
+}
+
+// The settings of one section, with `prefix` in front of the names in error messages.
+fn read_overrides(table: &Table, prefix: &str, errors: &mut Vec<String>) -> Overrides {
+    let mut overrides = Overrides::default();
+    for (name, value) in table {
+        let full_name = format!("{prefix}{name}");
+        match name.as_str() {
+            "tab_width" => match number(value, 1, MAX_TAB_WIDTH) {
+                Some(width) => overrides.tab_width = usize::try_from(width).ok(),
+                None => errors.push(expected(&full_name, &format!("a number from 1 to {MAX_TAB_WIDTH}"), value)),
+            },
+            "line_numbers" => match value.as_bool() {
+                Some(enabled) => overrides.line_numbers = Some(enabled),
+                None => errors.push(expected(&full_name, "true or false", value)),
+            },
+            "wrap" => match value.as_str() {
+                Some("off") => overrides.wrap = Some(Wrap::Off),
+                Some("on") => overrides.wrap = Some(Wrap::Anywhere),
+                Some("words") => overrides.wrap = Some(Wrap::AtWords),
+                _ => errors.push(expected(&full_name, "\"off\", \"on\" or \"words\"", value)),
+            },
+            "autosave" => match number(value, 0, i64::MAX) {
+                Some(seconds) => overrides.autosave = Some(Duration::from_secs(seconds.unsigned_abs())),
+                None => errors.push(expected(&full_name, "a number of seconds, or 0 to turn it off", value)),
+            },
+            "rainbow_brackets" => match value.as_bool() {
+                Some(enabled) => overrides.rainbow_brackets = Some(enabled),
+                None => errors.push(expected(&full_name, "true or false", value)),
+            },
+            _ => errors.push(format!("{full_name}: there is no such setting")),
+        }
+    }
+    overrides
+}
+
+fn number(value: &Value, min: i64, max: i64) -> Option<i64> {
+    value.as_integer().filter(|number| (min..=max).contains(number))
+}
+
+fn expected(name: &str, what: &str, value: &Value) -> String {
+    format!("{name}: expected {what}, not {value}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lookup;
+    use termion::event::Key;
+
+    #[test]
+    fn settings() {
+        let config = Config::parse(
+            "theme = \"gruvbox-dark\"\nquit_times = 1\nmessage_timeout = 10\nline_numbers = true\nwrap = \"words\"\nautosave = 30\n",
+        );
+        assert!(config.errors().is_empty());
+        assert_eq!(config.theme(), Some("gruvbox-dark"));
+        assert_eq!(config.quit_times(), 1);
+        assert_eq!(config.message_timeout(), Duration::from_secs(10));
+        let settings = config.for_file_type("Text");
+        assert!(settings.line_numbers);
+        assert!(settings.wrap == Wrap::AtWords);
+        assert_eq!(settings.autosave, Some(Duration::from_secs(30)));
+        assert_eq!(settings.tab_width, None);
+    }
+
+    #[test]
+    fn file_types_override_the_general_settings() {
+        let config = Config::parse("autosave = 30\ntab_width = 8\n[filetype.rust]\ntab_width = 4\nautosave = 0\nrainbow_brackets = true\n");
+        assert!(config.errors().is_empty());
+        let rust = config.for_file_type("Rust");
+        assert_eq!(rust.tab_width, Some(4));
+        assert_eq!(rust.autosave, None);
+        assert!(rust.rainbow_brackets);
+        let text = config.for_file_type("Text");
+        assert_eq!(text.tab_width, Some(8));
+        assert_eq!(text.autosave, Some(Duration::from_secs(30)));
+        assert!(!text.rainbow_brackets);
+    }
+
+    #[test]
+    fn keys() {
+        let config = Config::parse("[keys]\nctrl-o = \"save\"\nctrl-s = \"none\"\n\"ctrl-k ctrl-w\" = \"wrap\"\n");
+        assert!(config.errors().is_empty());
+        let keymap = config.keymap();
+        assert!(matches!(keymap.lookup(&[Key::Ctrl('o')]), Lookup::Command("save")));
+        assert!(matches!(keymap.lookup(&[Key::Ctrl('s')]), Lookup::Unbound));
+        assert!(matches!(keymap.lookup(&[Key::Ctrl('k'), Key::Ctrl('w')]), Lookup::Command("wrap")));
+    }
+
+    #[test]
+    fn invalid_entries_are_reported_and_left_out() {
+        let config = Config::parse(concat!(
+            "theme = \"nope\"\nquit_times = 300\nmessage_timeout = 0\ntab_width = 0\nwrap = \"sometimes\"\n",
+            "autosave = -1\nline_numbers = \"yes\"\ncolour = 1\n",
+            "[keys]\nctrl-o = \"fly\"\nhyper-x = \"save\"\n[filetype.rust]\ntab_width = 33\n",
+        ));
+        let errors = config.errors();
+        assert_eq!(errors.len(), 11, "{errors:?}");
+        for part in [
+            "theme: expected one of",
+            "quit_times: expected a number from 0 to 255, not 300",
+            "message_timeout: expected a number of seconds, not 0",
+            "tab_width: expected a number from 1 to 32, not 0",
+            "wrap: expected \"off\", \"on\" or \"words\", not \"sometimes\"",
+            "autosave: expected a number of seconds, or 0 to turn it off, not -1",
+            "line_numbers: expected true or false, not \"yes\"",
+            "colour: there is no such setting",
+            "keys.ctrl-o: expected \"none\" or the name of a command, not \"fly\"",
+            "keys: \"hyper-x\" isn't a key",
+            "filetype.rust.tab_width: expected a number from 1 to 32, not 33",
+        ] {
+            assert!(errors.iter().any(|error| error.starts_with(part)), "{part}");
+        }
+        assert_eq!(config.theme(), None);
+        assert_eq!(config.quit_times(), QUIT_TIMES);
+        assert_eq!(config.message_timeout(), MESSAGE_TIMEOUT);
+        let settings = config.for_file_type("Rust");
+        assert_eq!(settings.tab_width, None);
+        assert!(settings.wrap == Wrap::Off);
+        assert!(matches!(config.keymap().lookup(&[Key::Ctrl('o')]), Lookup::Unbound));
+    }
+
+    #[test]
+    fn syntax_errors_say_where() {
+        let config = Config::parse("quit_times = 1\nwrap = \n");
+        assert_eq!(config.errors().len(), 1);
+        assert!(config.errors()[0].ends_with("on line 2"), "{}", config.errors()[0]);
+        assert_eq!(config.quit_times(), QUIT_TIMES);
+    }
+}
@@ -2,47 +2,185 @@ use crate::FileType;
 use crate::Row;
 use crate::Position;
 use crate::SearchDirection;
+use crate::highlighting;
+use crate::syntax::Syntax;
+use crate::editorconfig::{EditorConfig, IndentStyle};
+use std::cmp;
 use std::fs;
-use std::io::{Error, Write};
+use std::ops::Range;
+use std::io::Error;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+use std::time::SystemTime;
+use tree_sitter::{InputEdit, Point};
 
+/// A file being edited, as rows of text. Positions in it are grapheme indexes into a row.
 #[derive(Default)]
 pub struct Document {
     rows: Vec<Row>,
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    highlighted_word: Option<String>,
+    first_stale_row: usize, // rows above this one are known to be highlighted correctly
+    syntax: Option<Syntax>,
+    rainbow_brackets: bool,
+    disk_modified: Option<SystemTime>, // when the file was last changed as far as we know
+    tab_width: Option<usize>, // overrides the file type's
+    editorconfig: EditorConfig, // which overrides everything else
+    row_offsets: Vec<usize>, // where the first rows start in the text tree-sitter sees, as far as it's known
+}
+
+/// A document with nothing from a file or its `.editorconfig` files, only `text`.
+impl From<&str> for Document {
+    fn from(text: &str) -> Self {
+        Self {
+            rows: text.lines().map(Row::from).collect(),
+            ..Self::default()
+        }
+    }
 }
 
 impl Document {
+    /// A document without a file name, such as one read from stdin. The `.editorconfig` files
+    /// above the current directory apply to it the way they would to a file there, but only
+    /// their sections for any file, like `[*]`, can match. Bytes that don't make sense in its
+    /// `charset` become replacement characters.
+    pub fn unnamed(bytes: &[u8]) -> Self {
+        let editorconfig = EditorConfig::for_unnamed_file();
+        let text = editorconfig.decode(bytes.to_vec()).unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned());
+        let rows = editorconfig.lines(&text).into_iter().map(Row::from).collect();
+        Self {
+            rows,
+            editorconfig,
+            ..Self::default()
+        }
+    }
+
+    /// Loads `filename`, picking the file type from its name and reading it as the
+    /// `.editorconfig` files above it say.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, or isn't valid UTF-8 when it should be.
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let mut rows = Vec::new();
-        let file_contents = fs::read_to_string(filename)?;
+        let editorconfig = EditorConfig::for_file(Path::new(filename));
+        let file_contents = editorconfig.decode(fs::read(filename)?)?;
         let file_type = FileType::from(filename);
-        for line in file_contents.lines() {
-            rows.push(Row::from(line));
-        }
+        let syntax = Syntax::new(&file_type);
+        let rows = editorconfig.lines(&file_contents).into_iter().map(Row::from).collect();
 
         Ok(Self { 
             rows,
             file_name: Some(filename.to_string()),
             dirty: false,
             file_type,
+            highlighted_word: None,
+            first_stale_row: 0,
+            syntax,
+            rainbow_brackets: false,
+            disk_modified: modified_time(filename),
+            tab_width: None,
+            editorconfig,
+            row_offsets: Vec::new(),
         })
     }
 
+    /// Writes the document back to its file, if it has a name, and picks the file type again
+    /// in case the name changed. The `.editorconfig` files are read again too, and with
+    /// `trim_trailing_whitespace` the rows lose their trailing white space, whether there is a
+    /// file to write them to or not.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be written.
     pub fn save(&mut self) -> Result<(), Error> {
         if let Some(file_name) = &self.file_name {
-            self.file_type = FileType::from(&file_name);
-            let mut file = fs::File::create(file_name)?;
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+            self.editorconfig = EditorConfig::for_file(Path::new(file_name));
+        }
+        if self.editorconfig.trim_trailing_whitespace() {
+            self.trim_trailing_whitespace();
+        }
+        if let Some(file_name) = self.file_name.clone() {
+            fs::write(&file_name, self.contents())?;
+            self.disk_modified = modified_time(&file_name);
+
+            let file_type = FileType::from(file_name.as_str());
+            if file_type.name() != self.file_type.name() {
+                self.syntax = Syntax::new(&file_type);
+                self.file_type = file_type;
+                self.unhighlight_all();
             }
         }
         self.dirty = false;
         Ok(())
     }
 
+    /// The text as it would be saved: every row ending in a line break, unless an `.editorconfig`
+    /// says to leave it off the last one, and in the line ending and charset it asks for.
+    pub fn contents(&self) -> Vec<u8> {
+        let line_ending = self.editorconfig.line_ending();
+        let mut contents = String::new();
+        for (index, row) in self.rows.iter().enumerate() {
+            if index > 0 {
+                contents.push_str(line_ending);
+            }
+            contents.push_str(&String::from_utf8_lossy(row.as_bytes()));
+        }
+        if !self.rows.is_empty() && self.editorconfig.insert_final_newline() {
+            contents.push_str(line_ending);
+        }
+        self.editorconfig.encode(&contents)
+    }
+
+    // In one pass, keeping count of where each row starts as the ones before it get shorter.
+    fn trim_trailing_whitespace(&mut self) {
+        let mut offset = 0_usize;
+        for y in 0..self.rows.len() {
+            let Some(row) = self.rows.get(y) else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(row.as_bytes());
+            let length = text.trim_end().graphemes(true).count();
+            let (row_length, column, end_column) = (row.len(), row.byte_index(length), row.as_bytes().len());
+            if length < row_length {
+                let start = (offset.saturating_add(column), Point::new(y, column));
+                let end = (offset.saturating_add(end_column), Point::new(y, end_column));
+                self.edit_syntax(start, end, "");
+                if let Some(row) = self.rows.get_mut(y) {
+                    row.split(length);
+                }
+                self.unhighlight_row(y);
+            }
+            offset = offset.saturating_add(column).saturating_add(1);
+        }
+    }
+
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    /// For when the file was written by someone other than `save`, so it doesn't count as changed on disk.
+    pub fn note_written(&mut self) {
+        if let Some(file_name) = &self.file_name {
+            self.disk_modified = modified_time(file_name);
+        }
+    }
+
+    /// Whether the file was changed by something else since the last call, or since it was opened or saved.
+    pub fn changed_on_disk(&mut self) -> bool {
+        let Some(file_name) = &self.file_name else {
+            return false;
+        };
+        let modified = modified_time(file_name);
+        if modified == self.disk_modified {
+            return false;
+        }
+        self.disk_modified = modified;
+        true
+    }
+
+    /// The row at `index`, counting from 0.
     pub fn get_row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
@@ -51,14 +189,42 @@ impl Document {
         self.rows.is_empty()
     }
 
+    /// The name of the file type, such as "Rust".
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
 
+    /// The number of rows.
     pub fn len(&self) -> usize {
         self.rows.len()
     }
 
+    /// How many columns apart tab stops are.
+    pub fn tab_width(&self) -> usize {
+        self.editorconfig.tab_width()
+            .or(self.tab_width)
+            .unwrap_or_else(|| self.file_type.tab_width())
+    }
+
+    /// Puts tab stops `tab_width` columns apart, whatever the file type says. An `.editorconfig`
+    /// still has the last word.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = Some(tab_width.max(1));
+    }
+
+    /// What the tab key puts in at `at`: a tab, or spaces up to the next indentation level when
+    /// an `.editorconfig` asks for `indent_style = space`.
+    pub fn indentation(&self, at: &Position) -> String {
+        if self.editorconfig.indent_style() != Some(IndentStyle::Space) {
+            return "\t".to_string();
+        }
+        let tab_width = self.tab_width();
+        let size = self.editorconfig.indent_size(tab_width).max(1);
+        let column = self.rows.get(at.y).map_or(0, |row| row.column(at.x, tab_width));
+        " ".repeat(size.saturating_sub(column % size))
+    }
+
+    /// Deletes the grapheme at `at`, or joins the next row onto this one when `at` is at the end of it.
     #[allow(clippy::integer_arithmetic)]
     pub fn delete(&mut self, at: &Position) {
         let len = self.rows.len();
@@ -70,16 +236,24 @@ impl Document {
         self.dirty = true;
         
         if at.x == self.rows[at.y].len() && at.y + 1 < len {
+            let start = self.text_position(at);
+            self.edit_syntax(start, (start.0 + 1, Point::new(at.y + 1, 0)), "");
             let next_row = self.rows.remove(at.y + 1);
             let current_row = &mut self.rows[at.y];
             current_row.append(next_row);
         } else {
+            if at.x < self.rows[at.y].len() {
+                let start = self.text_position(at);
+                let deleted = self.rows[at.y].byte_index(at.x + 1) - start.1.column;
+                self.edit_syntax(start, (start.0 + deleted, Point::new(at.y, start.1.column + deleted)), "");
+            }
             let current_row = &mut self.rows[at.y];
             current_row.delete(at.x);
         }
-        self.unhighlight_rows(at.y);
+        self.unhighlight_row(at.y);
     }
 
+    /// Splits the row at `at`, or adds an empty row when `at` is just past the last one.
     pub fn insert_newline(&mut self, at: &Position) {
         if at.y > self.rows.len() { //how would that even happen
             return;
@@ -87,18 +261,28 @@ impl Document {
 
         self.dirty = true;
 
-        if at.y == self.rows.len() || at.y + 1 == self.len() {
+        if at.y == self.rows.len() {
+            if !self.rows.is_empty() {
+                let end = self.end_of_text();
+                self.edit_syntax(end, end, "\n");
+            }
             self.rows.push(Row::default());
+            self.unhighlight_row(at.y);
             return;
         } 
 
+        let start = self.text_position(at);
+        self.edit_syntax(start, start, "\n");
+
         #[allow(clippy::indexing_slicing)] 
         let current_row = &mut self.rows[at.y];
         let new_row = current_row.split(at.x);
         #[allow(clippy::integer_arithmetic)]
         self.rows.insert(at.y + 1, new_row);
+        self.unhighlight_row(at.y);
     }
 
+    /// Inserts `c` at `at`, which may be just past the last row to start a new one.
     pub fn insert(&mut self, at: &Position, c: char) {
         if at.y > self.rows.len() {
             return;
@@ -109,20 +293,71 @@ impl Document {
         if c == '\n' {
             self.insert_newline(at);
         } else if at.y == self.rows.len() {
+            let end = self.end_of_text();
+            let inserted = if self.rows.is_empty() {
+                c.to_string()
+            } else {
+                format!("\n{c}")
+            };
+            self.edit_syntax(end, end, &inserted);
             let mut row = Row::default();
             row.insert(0, c);
             self.rows.push(row);
         } else {
+            let start = self.text_position(at);
+            self.edit_syntax(start, start, c.encode_utf8(&mut [0; 4]));
             #[allow(clippy::indexing_slicing)]
             let current_row = &mut self.rows[at.y];
             current_row.insert(at.x, c);
         }
+        self.unhighlight_row(at.y);
+    }
+
+    /// Puts all of `text` in at once rather than a character at a time, and returns where it ends.
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if at.y > self.rows.len() || text.is_empty() {
+            return at.clone();
+        }
+
+        self.dirty = true;
+
+        if at.y == self.rows.len() {
+            let end = self.end_of_text();
+            let inserted = if self.rows.is_empty() {
+                text.to_string()
+            } else {
+                format!("\n{text}")
+            };
+            self.edit_syntax(end, end, &inserted);
+            self.rows.push(Row::default());
+        } else {
+            let start = self.text_position(at);
+            self.edit_syntax(start, start, text);
+        }
+
+        let mut lines = text.split('\n');
+        #[allow(clippy::indexing_slicing)]
+        let current_row = &mut self.rows[at.y];
+        let rest = current_row.split(at.x);
+        current_row.append(Row::from(lines.next().unwrap_or_default()));
+        let added: Vec<Row> = lines.map(Row::from).collect();
+        let y = at.y.saturating_add(added.len());
+        self.rows.splice(at.y.saturating_add(1)..at.y.saturating_add(1), added);
+
+        #[allow(clippy::indexing_slicing)]
+        let last_row = &mut self.rows[y];
+        let x = last_row.len();
+        last_row.append(rest);
+        self.unhighlight_row(at.y);
+        Position { x, y }
     }
 
+    /// Whether there are changes that haven't been saved.
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
 
+    /// The next place `query` shows up from `at` on, going in `search_direction`.
     pub fn find(&self, query: &str, at: &Position, search_direction: SearchDirection) -> Option<Position> {
         if at.y >= self.rows.len() {
             return None;
@@ -163,8 +398,89 @@ impl Document {
         None
     }
 
-    pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
-        let mut start_with_comment = false;
+    /// The bracket pairing up with the one at `at`, skipping brackets in strings and comments.
+    /// Gives up at rows that aren't highlighted yet, since those can't be told apart.
+    pub fn matching_bracket(&self, at: &Position) -> Option<Position> {
+        self.matching_bracket_within(at, 0..self.rows.len())
+    }
+
+    // Like `matching_bracket`, but only looking at `rows`, for when anything further away
+    // doesn't matter and scanning all the way there would take too long.
+    pub(crate) fn matching_bracket_within(&self, at: &Position, rows: Range<usize>) -> Option<Position> {
+        let row = self.rows.get(at.y)?;
+        let (_, bracket) = row.brackets().into_iter().find(|(x, _)| *x == at.x)?;
+        let (partner, forward) = match bracket {
+            '(' => (')', true),
+            '[' => (']', true),
+            '{' => ('}', true),
+            ')' => ('(', false),
+            ']' => ('[', false),
+            '}' => ('{', false),
+            _ => return None,
+        };
+
+        let mut depth: usize = 0;
+        let mut matches = |x: usize, y: usize, c: char| -> Option<Position> {
+            if c == bracket {
+                depth = depth.saturating_add(1);
+            } else if c == partner {
+                if depth == 0 {
+                    return Some(Position { x, y });
+                }
+                depth = depth.saturating_sub(1);
+            }
+            None
+        };
+
+        if forward {
+            for (y, row) in self.rows.iter().enumerate().take(rows.end).skip(at.y) {
+                if !row.is_highlighted {
+                    return None;
+                }
+                for (x, c) in row.brackets() {
+                    if y > at.y || x > at.x {
+                        if let Some(position) = matches(x, y, c) {
+                            return Some(position);
+                        }
+                    }
+                }
+            }
+        } else {
+            for (y, row) in self.rows.iter().enumerate().take(at.y.saturating_add(1)).skip(rows.start).rev() {
+                if !row.is_highlighted {
+                    return None;
+                }
+                for (x, c) in row.brackets().into_iter().rev() {
+                    if y < at.y || x < at.x {
+                        if let Some(position) = matches(x, y, c) {
+                            return Some(position);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Works out the highlighting of every row up to and including `until`, or of all of them.
+    /// Rows that haven't changed since the last time are left as they are. Anywhere `word`
+    /// shows up is highlighted as a search match.
+    pub fn highlight(&mut self, word: Option<&str>, until: Option<usize>) {
+        if word != self.highlighted_word.as_deref() {
+            // search matches can be anywhere, so every row has to be redone
+            self.unhighlight_all();
+            self.highlighted_word = word.map(str::to_string);
+        }
+
+        if let Some(syntax) = &mut self.syntax {
+            for (start, end) in syntax.parse(&self.rows) {
+                for row in self.rows.iter_mut().take(end.saturating_add(1)).skip(start) {
+                    row.is_highlighted = false;
+                }
+                self.first_stale_row = cmp::min(self.first_stale_row, start);
+            }
+        }
+
         let until = if let Some(until) = until {
             if until.saturating_add(1) < self.rows.len() {
                 until.saturating_add(1)
@@ -174,16 +490,250 @@ impl Document {
         } else {
             self.rows.len()
         };
+        if self.first_stale_row >= until {
+            return;
+        }
+
+        let (mut start_state, mut depth) = match self.first_stale_row.checked_sub(1) {
+            Some(previous) => (self.rows[previous].end_state(), self.rows[previous].end_depth()),
+            None => (highlighting::State::None, 0),
+        };
+        // Rows that were highlighted before with the same start state return right away,
+        // so after an edit only the rows up to where the state converges again are redone.
         #[allow(clippy::indexing_slicing)]
-        for row in &mut self.rows[..until] {
-            start_with_comment = row.highlight(&self.file_type.highlighting_options(), word, start_with_comment);
+        for (index, row) in self.rows.iter_mut().enumerate().take(until).skip(self.first_stale_row) {
+            if let Some(syntax) = &self.syntax {
+                if !row.is_highlighted {
+                    let highlighting = syntax.highlight_row(index, row);
+                    row.set_highlighting(highlighting, word);
+                }
+            } else {
+                start_state = row.highlight(self.file_type.highlighting_options(), word, start_state);
+            }
+            if self.rainbow_brackets {
+                depth = row.highlight_brackets(depth);
+            }
         }
+        self.first_stale_row = until;
     }
 
-    fn unhighlight_rows(&mut self, start: usize) {
-        let start = start.saturating_sub(1);
-        for row in self.rows.iter_mut().skip(start) {
+    pub fn rainbow_brackets(&self) -> bool {
+        self.rainbow_brackets
+    }
+
+    pub fn set_rainbow_brackets(&mut self, enabled: bool) {
+        self.rainbow_brackets = enabled;
+        self.unhighlight_all();
+    }
+
+    fn unhighlight_all(&mut self) {
+        for row in &mut self.rows {
             row.is_highlighted = false;
         }
+        self.first_stale_row = 0;
+    }
+
+    fn unhighlight_row(&mut self, index: usize) {
+        if let Some(row) = self.rows.get_mut(index) {
+            row.is_highlighted = false;
+        }
+        self.first_stale_row = cmp::min(self.first_stale_row, index);
+    }
+
+    // Where `at` is in the text tree-sitter sees, which is the rows joined by newlines.
+    fn text_position(&mut self, at: &Position) -> (usize, Point) {
+        let column = self.rows.get(at.y).map_or(0, |row| row.byte_index(at.x));
+        (self.row_offset(at.y).saturating_add(column), Point::new(at.y, column))
+    }
+
+    // Offsets are only added up past the last one known, and an edit only forgets the ones after
+    // its row, so typing along a row doesn't go through all the rows above it every time.
+    fn row_offset(&mut self, y: usize) -> usize {
+        if self.row_offsets.is_empty() {
+            self.row_offsets.push(0);
+        }
+        while self.row_offsets.len() <= y {
+            let last = self.row_offsets.len().saturating_sub(1);
+            let length = self.rows.get(last).map_or(0, |row| row.as_bytes().len());
+            let offset = self.row_offsets.last().copied().unwrap_or(0).saturating_add(length).saturating_add(1);
+            self.row_offsets.push(offset);
+        }
+        self.row_offsets.get(y).copied().unwrap_or(0)
     }
-}
\ No newline at end of file
+
+    fn end_of_text(&mut self) -> (usize, Point) {
+        match self.rows.last() {
+            Some(row) => {
+                let end = Position { x: row.len(), y: self.rows.len().saturating_sub(1) };
+                self.text_position(&end)
+            },
+            None => (0, Point::new(0, 0)),
+        }
+    }
+
+    // Every edit comes through here before the rows change, so this is where the offsets of the
+    // rows after it are forgotten.
+    fn edit_syntax(&mut self, start: (usize, Point), old_end: (usize, Point), inserted: &str) {
+        self.row_offsets.truncate(start.1.row.saturating_add(1));
+        if let Some(syntax) = &mut self.syntax {
+            let new_end_position = match inserted.rfind('\n') {
+                Some(newline) => Point::new(start.1.row + inserted.matches('\n').count(), inserted.len() - newline - 1),
+                None => Point::new(start.1.row, start.1.column + inserted.len()),
+            };
+            syntax.edit(&InputEdit {
+                start_byte: start.0,
+                old_end_byte: old_end.0,
+                new_end_byte: start.0 + inserted.len(),
+                start_position: start.1,
+                old_end_position: old_end.1,
+                new_end_position,
+            });
+        }
+    }
+}
+
+fn modified_time(file_name: &str) -> Option<SystemTime> {
+    fs::metadata(file_name).and_then(|metadata| metadata.modified()).ok()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What `text_position` works out bit by bit, added up from the first row.
+    fn offsets(document: &Document) -> Vec<usize> {
+        document.rows.iter().scan(0, |offset, row| {
+            let start = *offset;
+            *offset += row.as_bytes().len() + 1;
+            Some(start)
+        }).collect()
+    }
+
+    #[test]
+    fn a_newline_splits_the_last_row_too() {
+        let mut document = Document::from("first\nsecond");
+        document.insert_newline(&Position { x: 3, y: 1 });
+        assert_eq!(document.contents(), b"first\nsec\nond\n");
+        document.insert_newline(&Position { x: 0, y: 3 });
+        assert_eq!(document.contents(), b"first\nsec\nond\n\n");
+    }
+
+    #[test]
+    fn bracket_search_stays_within_the_rows_given() {
+        let mut document = Document::from("{\n(\n)\n}");
+        document.highlight(None, None);
+        let at = Position { x: 0, y: 3 };
+        assert_eq!(document.matching_bracket(&at).map(|found| (found.x, found.y)), Some((0, 0)));
+        assert!(document.matching_bracket_within(&at, 1..4).is_none());
+        let inner = Position { x: 0, y: 1 };
+        assert_eq!(document.matching_bracket_within(&inner, 1..3).map(|found| found.y), Some(2));
+        assert!(document.matching_bracket_within(&inner, 1..2).is_none());
+    }
+
+    #[test]
+    fn row_offsets_follow_edits() {
+        let mut document = Document::from("fn main() {\n    let a = 1;\n}\n\nfn other() {}");
+        let starts: Vec<usize> = (0..document.len()).map(|y| document.text_position(&Position { x: 0, y }).0).collect();
+        assert_eq!(starts, offsets(&document));
+
+        document.insert(&Position { x: 15, y: 1 }, '\u{e9}');
+        document.insert_str(&Position { x: 0, y: 3 }, "// a comment\nconst B: u8 = 2;");
+        document.delete(&Position { x: 11, y: 0 });
+        document.insert_newline(&Position { x: 11, y: 0 });
+        document.delete(&Position { x: 0, y: 2 });
+        document.insert(&Position { x: 0, y: document.len() }, 'x');
+        for (y, start) in offsets(&document).into_iter().enumerate() {
+            assert_eq!(document.text_position(&Position { x: 0, y }).0, start, "row {y}");
+        }
+    }
+
+    #[test]
+    fn trimmed_rows_highlight_like_freshly_read_ones() {
+        let rust = |text: &str| {
+            let file_type = FileType::from("main.rs");
+            let mut document = Document {
+                syntax: Syntax::new(&file_type),
+                file_type,
+                ..Document::from(text)
+            };
+            document.highlight(None, None);
+            document
+        };
+        let mut document = rust("fn main() {      \n    let a = \"b     \n c\";\t\t\n\n    call(a, 1);  \n    /* x    \n y */ 2 \n}   ");
+        document.trim_trailing_whitespace();
+        document.highlight(None, None);
+        let trimmed = "fn main() {\n    let a = \"b\n c\";\n\n    call(a, 1);\n    /* x\n y */ 2\n}";
+        assert_eq!(document.contents(), format!("{trimmed}\n").as_bytes());
+        let fresh = rust(trimmed);
+        for (y, (row, expected)) in document.rows.iter().zip(&fresh.rows).enumerate() {
+            assert_eq!(format!("{:?}", row.highlighting()), format!("{:?}", expected.highlighting()), "row {y}");
+        }
+    }
+
+    // Rust, but with the plain scanner rather than tree-sitter.
+    fn scanned(text: &str) -> Document {
+        let mut document = Document {
+            file_type: FileType::from("main.rs"),
+            ..Document::from(text)
+        };
+        document.highlight(None, None);
+        document
+    }
+
+    fn highlighting(document: &Document) -> Vec<Vec<highlighting::Type>> {
+        document.rows.iter().map(|row| row.highlighting().to_vec()).collect()
+    }
+
+    #[test]
+    fn opening_and_closing_a_comment_or_string_redoes_the_rows_below() {
+        let text = "let a = 1;\nlet b = \"x\";\nfn c() {}";
+        for opener in ["/*", "\""] {
+            let mut document = scanned(text);
+            let before = highlighting(&document);
+
+            document.insert_str(&Position { x: 0, y: 0 }, opener);
+            document.highlight(None, None);
+            let opened = highlighting(&document);
+            assert!(opened[2].iter().all(|kind| *kind != highlighting::Type::PrimaryKeywords), "{opener}: {:?}", opened[2]);
+            assert_ne!(opened[1], before[1], "{opener}");
+
+            for _ in 0..opener.len() {
+                document.delete(&Position { x: 0, y: 0 });
+            }
+            document.highlight(None, None);
+            assert_eq!(highlighting(&document), before, "{opener}");
+        }
+    }
+
+    #[test]
+    fn highlighting_stops_where_the_state_is_the_same_as_before() {
+        let mut document = scanned("/* a\nb */ let c;\nlet d;\nlet e;");
+        // a row the scanner would never come up with, to see whether it gets redone
+        let marked = vec![highlighting::Type::Number; 6];
+        document.rows[3].set_highlighting(marked.clone(), None);
+
+        // the comment still ends on the second row, so nothing changes for the rows after it
+        document.insert(&Position { x: 3, y: 0 }, 'x');
+        document.highlight(None, None);
+        assert_eq!(document.rows[1].highlighting()[..4], [highlighting::Type::MultiLineComment; 4]);
+        assert_eq!(document.rows[3].highlighting(), marked);
+
+        // now it doesn't, and every row below has to be redone
+        document.delete(&Position { x: 2, y: 1 });
+        document.highlight(None, None);
+        assert_eq!(document.rows[3].highlighting(), [highlighting::Type::MultiLineComment; 6]);
+    }
+
+    #[test]
+    fn rainbow_depth_goes_on_from_row_to_row() {
+        let mut document = scanned("fn f() {\n    g([1]);\n}\n)");
+        document.set_rainbow_brackets(true);
+        document.highlight(None, None);
+        let depths: Vec<Vec<usize>> = document.rows.iter().map(|row| {
+            row.highlighting().iter().filter_map(|kind| match kind {
+                highlighting::Type::Bracket(depth) => Some(*depth),
+                _ => None,
+            }).collect()
+        }).collect();
+        assert_eq!(depths, [vec![0, 0, 0], vec![1, 2, 2, 1], vec![0], vec![0]]);
+    }
+}
@@ -1,33 +1,102 @@
 use crate::Terminal;
 use crate::Document;
+use crate::Command;
+use crate::config::Config;
+use crate::keymap::{self, Lookup};
 use crate::Row;
+use crate::Theme;
+use crate::terminal::{Backend, ColorSupport, Event};
+use crate::jobs::Jobs;
+use crate::screen::Screen;
+use crate::theme::Style;
 use crate::document;
 use crate::row;
-use termion::raw::IntoRawMode;
-use termion::event::Key;
-use termion::color;
+use termion::event::{Key, MouseButton, MouseEvent};
 use termion::input::TermRead;
-use std::io::{self, stdout, Write};
+use std::io::{ErrorKind, Write};
+use std::cmp;
+use std::ops::Range;
 use std::env;
+use std::fs;
 use std::time::Instant;
 use std::time::Duration;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-const STATUS_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-const QUIT_TIMES: u8 = 3;
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const WHEEL_LINES: usize = 3;
+// For turning autosave on when the configuration doesn't say after how long.
+const AUTOSAVE_AFTER: Duration = Duration::from_secs(30);
+const PALETTE_LINES: usize = 10;
+// How far off the screen the partner of the bracket at the cursor is looked for.
+const BRACKET_SCAN_ROWS: usize = 500;
 
+/// A place in a document: `y` is the row and `x` the grapheme in it.
 #[derive(Default, Clone)]
 pub struct Position {
     pub x: usize,
     pub y: usize
 }
 
+/// Which way a search goes from where it starts.
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
     Forward,
     Backward,
 }
+/// How rows too long for the screen are shown: scrolled sideways, or wrapped onto more screen lines.
+#[derive(PartialEq, Copy, Clone)]
+pub enum Wrap {
+    Off,
+    Anywhere,
+    AtWords,
+}
+
+/// A named editor action, which key sequences can be bound to.
+pub struct EditorCommand {
+    name: &'static str,
+    description: &'static str,
+    run: fn(&mut Editor),
+}
+
+impl EditorCommand {
+    /// What the configuration file calls it, such as `save`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// What it does, in a few words.
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+}
+
+const COMMANDS: &[EditorCommand] = &[
+    EditorCommand { name: "command-palette", description: "Search the commands and run one", run: Editor::command_palette },
+    EditorCommand { name: "quit", description: "Quit", run: Editor::quit },
+    EditorCommand { name: "save", description: "Save", run: Editor::save },
+    EditorCommand { name: "find", description: "Find", run: Editor::search },
+    EditorCommand { name: "go-to-line", description: "Go to line", run: Editor::go_to_line },
+    EditorCommand { name: "matching-bracket", description: "Jump to the matching bracket", run: Editor::jump_to_matching_bracket },
+    EditorCommand { name: "rainbow-brackets", description: "Turn rainbow brackets on or off", run: Editor::toggle_rainbow_brackets },
+    EditorCommand { name: "line-numbers", description: "Turn line numbers on or off", run: Editor::toggle_line_numbers },
+    EditorCommand { name: "wrap", description: "Cycle soft wrap: off, on, at word boundaries", run: Editor::cycle_wrap },
+    EditorCommand { name: "theme", description: "Switch to the next theme", run: Editor::next_theme },
+    EditorCommand { name: "autosave", description: "Turn autosave on or off", run: Editor::toggle_autosave },
+    EditorCommand { name: "cursor-up", description: "Move up", run: |editor| editor.move_cursor(Key::Up) },
+    EditorCommand { name: "cursor-down", description: "Move down", run: |editor| editor.move_cursor(Key::Down) },
+    EditorCommand { name: "cursor-left", description: "Move left", run: |editor| editor.move_cursor(Key::Left) },
+    EditorCommand { name: "cursor-right", description: "Move right", run: |editor| editor.move_cursor(Key::Right) },
+    EditorCommand { name: "line-start", description: "Move to the start of the line", run: |editor| editor.move_cursor(Key::Home) },
+    EditorCommand { name: "line-end", description: "Move to the end of the line", run: |editor| editor.move_cursor(Key::End) },
+    EditorCommand { name: "page-up", description: "Move a screen up", run: |editor| editor.move_cursor(Key::PageUp) },
+    EditorCommand { name: "page-down", description: "Move a screen down", run: |editor| editor.move_cursor(Key::PageDown) },
+    EditorCommand { name: "newline", description: "Break the line", run: |editor| editor.insert_char('\n') },
+    EditorCommand { name: "indent", description: "Indent with a tab or spaces", run: Editor::indent },
+    EditorCommand { name: "delete-backward", description: "Delete the character before the cursor", run: Editor::delete_backward },
+    EditorCommand { name: "delete-forward", description: "Delete the character under the cursor", run: Editor::delete_forward },
+];
+
 pub struct StatusMessage {
     text: String,
     time: Instant,
@@ -41,21 +110,49 @@ impl StatusMessage {
     }
 }
 
+/// The editor's terminal interface, around a single document.
 pub struct Editor {
     should_quit: bool,
-    terminal: Terminal,
+    terminal: Box<dyn Backend>,
     cursor_position: Position,
     document: Document,
-    offset: Position,
+    offset: Position, // with wrapping on, x is how many screen lines of row y are scrolled away
+    wrap: Wrap,
+    line_numbers: bool,
+    selection_anchor: Option<Position>, // where the selection started, it goes from there to the cursor
+    last_click: Option<(Instant, (usize, usize), u8)>, // when, where on the screen and how many in a row
     status_message: StatusMessage,
     quit_times: u8,
     highlighted_word: Option<String>,
+    theme: Theme,
+    jobs: Jobs,
+    autosave: Option<Duration>, // after how long without typing, if at all
+    last_edit: Instant,
+    next_file_check: Instant,
+    filter: bool, // started as `hecto -`, reading stdin and saving to stdout
+    output: Option<Vec<u8>>, // what a filter saved last
+    config: Config,
+    pending_keys: Vec<Key>, // the start of a key sequence, waiting for the rest
+    last_command: Option<&'static str>, // None after typing
+    palette: Option<Palette>,
 }
 
-impl Editor {
-    pub fn run(&mut self) {
-        let _stdout = stdout().into_raw_mode().unwrap();
+// The commands matching what was typed into the command palette, best match first.
+struct Palette {
+    matches: Vec<&'static EditorCommand>,
+    selected: usize,
+}
 
+impl Editor {
+    /// Handles input until the user quits, or until there is no more of it.
+    ///
+    /// # Errors
+    ///
+    /// If the input was closed before the user quit, for instance because a [`VirtualTerminal`]
+    /// ran out of events. The document may still have unsaved changes then.
+    ///
+    /// [`VirtualTerminal`]: crate::VirtualTerminal
+    pub fn run(&mut self) -> Result<(), std::io::Error> {
         loop {
             if let Err(error) = self.refresh_screen() {
                 die(error);
@@ -63,44 +160,157 @@ impl Editor {
 
 
             if self.should_quit {
-                break;
+                return Ok(());
             } 
 
-            if let Err(error) = self.process_keypress() {
-                die(error);
+            match self.read_input() {
+                Ok(Event::Key(key)) => self.process_keypress(key),
+                Ok(Event::Paste(text)) => self.paste(&text),
+                Ok(_) => (),
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Err(error),
+                Err(error) => die(error),
             }
         }
     }
 
+    /// An editor on the real terminal, with the file named on the command line open and the
+    /// settings from the configuration file.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal can't be set up.
     pub fn default() -> Self {
         let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("Tip: Ctrl-F -> Find | Ctrl-S -> Save | Ctrl-Q -> Quit");
-        let document = if let Some(file_name) = args.get(1) {
-            let file = Document::open(&file_name);
+        let terminal = Terminal::default().expect("Failed to initialize terminal.");
+        Self::with_config(Box::new(terminal), args.get(1).map(String::as_str), Config::load())
+    }
+
+    /// An editor on `terminal` rather than the real one, with `file_name` open if there is one.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal can't hand out a waker.
+    pub fn with_terminal(terminal: Box<dyn Backend>, file_name: Option<&str>) -> Self {
+        Self::with_config(terminal, file_name, Config::default())
+    }
+
+    /// Like [`Editor::with_terminal`], with the settings from `config`. The first of its errors, if it
+    /// has any, is shown in the message bar.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal can't hand out a waker.
+    pub fn with_config(terminal: Box<dyn Backend>, file_name: Option<&str>, config: Config) -> Self {
+        let mut error = None;
+        let document = if let Some(file_name) = file_name {
+            let file = Document::open(file_name);
             
             if let Ok(file) = file {
                 file
             } else {
-                initial_status = format!("ERROR: Could not open file - {}", file_name);
+                error = Some(format!("ERROR: Could not open file - {}", file_name));
                 Document::default()
             }
         } else {
-            Document::default()
+            Document::unnamed(&[])
         };
+        Self::new(terminal, document, config, false, error)
+    }
+
+    /// An editor for `document`, which didn't come from a file, the way `hecto -` edits what it
+    /// read from stdin. Saving keeps the text for [`Editor::into_output`] instead of writing a file.
+    ///
+    /// # Panics
+    ///
+    /// If the terminal can't hand out a waker.
+    pub fn with_document(terminal: Box<dyn Backend>, document: Document, config: Config) -> Self {
+        Self::new(terminal, document, config, true, None)
+    }
 
-        Self { 
+    fn new(terminal: Box<dyn Backend>, document: Document, config: Config, filter: bool, error: Option<String>) -> Self {
+        let tips = [("command-palette", "Commands"), ("find", "Find"), ("save", "Save"), ("quit", "Quit")];
+        let tips: Vec<String> = tips.iter().filter_map(|(command, description)| {
+            config.keymap().bindings(command).first().map(|keys| format!("{keys} -> {description}"))
+        }).collect();
+        let mut initial_status = format!("Tip: {}", tips.join(" | "));
+        if let Some(error) = config.errors().first() {
+            initial_status = match config.errors().len().saturating_sub(1) {
+                0 => format!("ERROR in config: {error}"),
+                more => format!("ERROR in config: {error} (and {more} more)"),
+            };
+        }
+        if let Some(error) = error {
+            initial_status = error;
+        }
+
+        let jobs = Jobs::new(terminal.waker().expect("Failed to initialize terminal."));
+        let theme = if terminal.colors() == ColorSupport::Monochrome {
+            Theme::by_name("monochrome").unwrap_or_default()
+        } else {
+            config.theme().and_then(Theme::by_name).unwrap_or_default()
+        };
+
+        let mut editor = Self { 
             should_quit : false,
-            terminal : Terminal::default().expect("Failed to initialize terminal."),
+            terminal,
             cursor_position: Position::default(),
             document,
             offset: Position::default(),
+            wrap: Wrap::Off,
+            line_numbers: false,
+            selection_anchor: None,
+            last_click: None,
             status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIMES,
+            quit_times: config.quit_times(),
             highlighted_word: None,
+            theme,
+            jobs,
+            autosave: None,
+            last_edit: Instant::now(),
+            next_file_check: Instant::now().checked_add(FILE_CHECK_INTERVAL).unwrap_or_else(Instant::now),
+            filter,
+            output: None,
+            config,
+            pending_keys: Vec::new(),
+            last_command: None,
+            palette: None,
+        };
+        editor.apply_file_settings();
+        editor
+    }
+
+    // Takes on the configured settings for the document's file type.
+    fn apply_file_settings(&mut self) {
+        let settings = self.config.for_file_type(&self.document.file_type());
+        if let Some(tab_width) = settings.tab_width {
+            self.document.set_tab_width(tab_width);
         }
+        self.line_numbers = settings.line_numbers;
+        self.wrap = settings.wrap;
+        self.offset.x = 0;
+        self.autosave = settings.autosave;
+        self.document.set_rainbow_brackets(settings.rainbow_brackets);
+    }
+
+    /// Whether the document came from stdin and should go back out on stdout.
+    pub fn is_filter(&self) -> bool {
+        self.filter
+    }
+
+    /// What a filter saved last, if anything. The editor goes away first, so the terminal is back to
+    /// normal by the time this is written out.
+    pub fn into_output(mut self) -> Option<Vec<u8>> {
+        self.output.take()
     }
 
     fn save(&mut self) {
+        if self.filter {
+            // without a file name, this only tidies up the rows as the `.editorconfig` files ask
+            let _ = self.document.save();
+            self.output = Some(self.document.contents());
+            self.status_message = StatusMessage::from("Saved, it goes to stdout when you quit.".to_string());
+            return;
+        }
         if self.document.file_name.is_none() {
             let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
             if new_name.is_none() {
@@ -111,33 +321,60 @@ impl Editor {
         self.document.file_name = new_name;
         }
 
-        if self.document.save().is_ok() {
+        let file_type = self.document.file_type();
+        if Command::Save.run(&mut self.document, &mut self.cursor_position).is_ok() {
+            // saving under a new name can make it another type of file, with other settings
+            if self.document.file_type() != file_type {
+                self.apply_file_settings();
+            }
             self.status_message = StatusMessage::from("File saved successfully!".to_string());
         } else {
             self.status_message = StatusMessage::from("There was an error saving this file!".to_string());
         }
     }
 
+    /// For after a panic: the changes go next to the file rather than over it, since the
+    /// document may be in whatever state the panic left it in.
+    pub fn emergency_save(&self) {
+        if !self.document.is_dirty() {
+            return;
+        }
+        let file_name = self.document.file_name.as_deref().unwrap_or("hecto");
+        let save_name = format!("{file_name}.save");
+        match fs::write(&save_name, self.document.contents()) {
+            Ok(()) => eprintln!("Unsaved changes were written to {save_name}."),
+            Err(error) => eprintln!("Could not write unsaved changes to {save_name}: {error}"),
+        }
+    }
+
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
-        let mut direction = SearchDirection::Forward;
 
         let query = self.prompt("Search (ESC to cancel, arrow keys to navigate): ", |editor, key, query|  {
-            let mut moved = false;
-            match key {
+            let found = match key {
                 Key::Right | Key::Down => {
-                    direction = SearchDirection::Forward;
+                    // past the match the cursor is on, back to it if there is no other
                     editor.move_cursor(Key::Right);
-                    moved = true;
-                }
-                Key::Left | Key::Up => direction = SearchDirection::Backward,
-                _ => direction = SearchDirection::Forward,
-            }
-             if let Some(position) = editor.document.find(&query, &editor.cursor_position, direction) {
-                editor.cursor_position = position;
+                    let found = Command::Search(query.clone(), SearchDirection::Forward)
+                        .run(&mut editor.document, &mut editor.cursor_position)
+                        .is_ok();
+                    if !found {
+                        editor.move_cursor(Key::Left);
+                    }
+                    found
+                },
+                Key::Left | Key::Up => Command::Search(query.clone(), SearchDirection::Backward)
+                    .run(&mut editor.document, &mut editor.cursor_position)
+                    .is_ok(),
+                // a longer query can still match right where the shorter one did
+                _ => editor
+                    .document
+                    .find(query, &editor.cursor_position, SearchDirection::Forward)
+                    .map(|position| editor.cursor_position = position)
+                    .is_some(),
+            };
+             if found {
                 editor.scroll();
-             } else if moved {
-                editor.move_cursor(Key::Left);
              }
              editor.highlighted_word = Some(query.to_string());
         }).unwrap_or(None);
@@ -149,56 +386,339 @@ impl Editor {
         self.highlighted_word = None;
     }
 
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Ctrl('q') => {
-                if self.document.is_dirty() && self.quit_times > 0 {
-                    self.status_message = StatusMessage::from(format!("Warning! Quitting without saving. Press Ctrl-Q {} more times to quit.", self.quit_times));
-                    self.quit_times -= 1;
-                    return Ok(());
-                } 
-                self.should_quit = true
+    fn go_to_line(&mut self) {
+        let Some(answer) = self.prompt("Go to line[:column]: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let mut numbers = answer.split(':').map(|number| number.trim().parse::<usize>());
+        let (Some(Ok(line)), Ok(column), None) = (numbers.next(), numbers.next().unwrap_or(Ok(1)), numbers.next()) else {
+            self.status_message = StatusMessage::from(format!("Not a line number: {answer}"));
+            return;
+        };
+        let position = Position {
+            x: column.saturating_sub(1),
+            y: line.saturating_sub(1),
+        };
+        // going somewhere can't fail
+        let _ = Command::Goto(position).run(&mut self.document, &mut self.cursor_position);
+        self.scroll();
+    }
+
+    fn jump_to_matching_bracket(&mut self) {
+        // the partner can be anywhere below, and only highlighted rows can be searched
+        self.document.highlight(self.highlighted_word.as_deref(), None);
+        if let Some(position) = self.document.matching_bracket(&self.cursor_position) {
+            self.cursor_position = position;
+            self.scroll();
+        } else {
+            self.status_message = StatusMessage::from("No matching bracket.".to_string());
+        }
+    }
+
+    // Waits for the next key or paste, keeping the screen, timers and jobs up to date in the meantime.
+    fn read_input(&mut self) -> Result<Event, std::io::Error> {
+        loop {
+            match self.terminal.read_event(self.next_deadline())? {
+                event @ (Event::Key(_) | Event::Paste(_)) => return Ok(event),
+                Event::Mouse(mouse) => self.handle_mouse(mouse),
+                Event::Resize => self.handle_resize(),
+                Event::Wake => {
+                    for completion in self.jobs.finished() {
+                        completion(self);
+                    }
+                },
+                Event::Timeout => self.run_timers(),
+            }
+            self.refresh_screen()?;
+        }
+    }
+
+    // How long until something needs doing even if no key is pressed.
+    fn next_deadline(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut deadlines = Vec::new();
+        if !self.status_message.text.is_empty() {
+            // so the message bar gets cleared once it's time
+            deadlines.extend(self.status_message.time.checked_add(self.config.message_timeout()).filter(|expiry| *expiry > now));
+        }
+        if self.document.file_name.is_some() {
+            deadlines.push(self.next_file_check);
+            if self.document.is_dirty() && !self.jobs.is_running() {
+                deadlines.extend(self.autosave.and_then(|autosave| self.last_edit.checked_add(autosave)));
+            }
+        }
+        deadlines.into_iter().min().map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    fn run_timers(&mut self) {
+        let now = Instant::now();
+        if now >= self.next_file_check {
+            self.next_file_check = now.checked_add(FILE_CHECK_INTERVAL).unwrap_or(now);
+            if self.document.changed_on_disk() {
+                self.status_message = StatusMessage::from("Warning! The file was changed on disk by another program.".to_string());
+            }
+        }
+
+        if let Some(autosave) = self.autosave {
+            let due = self.last_edit.checked_add(autosave).is_some_and(|due| now >= due);
+            if due && self.document.is_dirty() && !self.jobs.is_running() {
+                self.autosave();
+            }
+        }
+    }
+
+    // Writes a snapshot of the document on another thread, so a big file doesn't hold up typing.
+    fn autosave(&mut self) {
+        let Some(file_name) = self.document.file_name.clone() else {
+            return;
+        };
+        let contents = self.document.contents();
+        self.document.set_dirty(false);
+        self.jobs.spawn(move || {
+            let result = fs::write(&file_name, contents);
+            Box::new(move |editor: &mut Editor| {
+                if result.is_ok() {
+                    editor.document.note_written();
+                    editor.status_message = StatusMessage::from(format!("Autosaved {file_name}."));
+                } else {
+                    editor.document.set_dirty(true);
+                    editor.status_message = StatusMessage::from("There was an error autosaving this file!".to_string());
+                }
+            })
+        });
+    }
+
+    /// Every command there is, for binding keys to.
+    pub fn commands() -> &'static [EditorCommand] {
+        COMMANDS
+    }
+
+    // Keys add up until they make a bound sequence. Keys that aren't bound to anything type
+    // themselves, sequences that aren't are dropped. One that starts with a character was typing
+    // after all though, so that goes in, and the keys after it are taken one by one again.
+    fn process_keypress(&mut self, pressed_key: Key) {
+        self.selection_anchor = None;
+        self.pending_keys.push(pressed_key);
+        let command = match self.config.keymap().lookup(&self.pending_keys) {
+            Lookup::Command(name) => COMMANDS.iter().find(|command| command.name == name),
+            Lookup::Prefix => {
+                self.status_message = StatusMessage::from(format!("{} -", keymap::keys_name(&self.pending_keys)));
+                return;
             },
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
+            Lookup::Unbound => None,
+        };
+        let keys = std::mem::take(&mut self.pending_keys);
+        if keys.len() > 1 {
+            // the sequence so far was showing in the message bar
+            self.status_message = StatusMessage::from(String::new());
+        }
+        if let Some(command) = command {
+            self.run_command(command);
+            return;
+        }
+        self.last_command = None;
+        match keys.as_slice() {
+            [Key::Char(c), rest @ ..] => {
+                self.insert_char(*c);
+                self.after_command();
+                for key in rest {
+                    self.process_keypress(*key);
+                }
+            },
+            [_, _, ..] => {
+                self.after_command();
+                self.status_message = StatusMessage::from(format!("{} isn't bound to anything.", keymap::keys_name(&keys)));
             },
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
+            _ => self.after_command(),
+        }
+    }
+
+    // A click or a paste ends an unfinished key sequence. One that starts with a character was
+    // typing, so its characters go in first.
+    fn end_pending_keys(&mut self) {
+        let keys = std::mem::take(&mut self.pending_keys);
+        if keys.is_empty() {
+            return;
+        }
+        // the sequence so far was showing in the message bar
+        self.status_message = StatusMessage::from(String::new());
+        if matches!(keys.first(), Some(Key::Char(_))) {
+            for key in keys {
+                if let Key::Char(c) = key {
+                    self.insert_char(c);
                 }
             }
-            Key::Up 
-            | Key::Down 
-            | Key::Left 
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::Home
-            | Key::End => self.move_cursor(pressed_key),
-            _ => (),
         }
+    }
+
+    fn run_command(&mut self, command: &'static EditorCommand) {
+        self.last_command = Some(command.name);
+        (command.run)(self);
+        self.after_command();
+    }
 
+    // The command palette runs another command in the middle of its own, which is the last one then.
+    fn after_command(&mut self) {
         self.scroll();
-        if self.quit_times < QUIT_TIMES { //Some button other than Ctrl-Q was pressed, so reset
-            self.quit_times = QUIT_TIMES;
+        if self.last_command != Some("quit") && self.quit_times < self.config.quit_times() { //Something other than quit was done, so reset
+            self.quit_times = self.config.quit_times();
             self.status_message = StatusMessage::from(String::new());
         }
-        Ok(())
+        if self.document.is_dirty() {
+            self.last_edit = Instant::now();
+        }
+    }
+
+    fn quit(&mut self) {
+        if self.document.is_dirty() && self.quit_times > 0 {
+            let keys = self.config.keymap().bindings("quit").into_iter().next().unwrap_or_else(|| "quit".to_string());
+            self.status_message = StatusMessage::from(format!("Warning! Quitting without saving. Press {} {} more times to quit.", keys, self.quit_times));
+            self.quit_times -= 1;
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    fn command_palette(&mut self) {
+        let picked = self.pick_command().unwrap_or(None);
+        self.palette = None;
+        self.status_message = StatusMessage::from(String::new());
+        if let Some(command) = picked {
+            self.run_command(command);
+        }
+    }
+
+    // Like `prompt`, but with the matching commands listed above the status bar and picked with
+    // the arrow keys, so Enter on an empty query still runs something.
+    fn pick_command(&mut self) -> Result<Option<&'static EditorCommand>, std::io::Error> {
+        let mut query = String::new();
+        self.palette = Some(Palette {
+            matches: matching_commands(&query),
+            selected: 0,
+        });
+        loop {
+            self.status_message = StatusMessage::from(format!("Command (ESC to cancel, arrow keys to choose): {query}"));
+            self.refresh_screen()?;
+            let key = match self.read_input()? {
+                Event::Key(key) => key,
+                Event::Paste(text) => {
+                    query.extend(text.chars().take_while(|c| *c != '\n').filter(|c| !c.is_control()));
+                    Key::Null
+                },
+                _ => continue,
+            };
+            let Some(palette) = self.palette.as_mut() else {
+                return Ok(None);
+            };
+            match key {
+                Key::Char('\n') => return Ok(palette.matches.get(palette.selected).copied()),
+                Key::Esc => return Ok(None),
+                Key::Up | Key::Ctrl('p') => palette.selected = palette.selected.saturating_sub(1),
+                Key::Down | Key::Ctrl('n') => {
+                    palette.selected = cmp::min(palette.selected.saturating_add(1), palette.matches.len().saturating_sub(1));
+                },
+                Key::Backspace => {
+                    query.pop();
+                },
+                Key::Char(c) if !c.is_control() => query.push(c),
+                _ => (),
+            }
+            if !matches!(key, Key::Up | Key::Down | Key::Ctrl('p' | 'n')) {
+                palette.matches = matching_commands(&query);
+                palette.selected = 0;
+            }
+        }
+    }
+
+    fn toggle_rainbow_brackets(&mut self) {
+        let enabled = !self.document.rainbow_brackets();
+        self.document.set_rainbow_brackets(enabled);
+        let state = if enabled { "on" } else { "off" };
+        self.status_message = StatusMessage::from(format!("Rainbow brackets {state}."));
+    }
+
+    fn toggle_autosave(&mut self) {
+        if self.autosave.take().is_some() {
+            self.status_message = StatusMessage::from("Autosave off.".to_string());
+            return;
+        }
+        let autosave = self.config.for_file_type(&self.document.file_type()).autosave.unwrap_or(AUTOSAVE_AFTER);
+        self.autosave = Some(autosave);
+        self.status_message = StatusMessage::from(format!("Autosave on, {} seconds after the last change.", autosave.as_secs()));
+    }
+
+    fn toggle_line_numbers(&mut self) {
+        self.line_numbers = !self.line_numbers;
+        let state = if self.line_numbers { "on" } else { "off" };
+        self.status_message = StatusMessage::from(format!("Line numbers {state}."));
+    }
+
+    fn cycle_wrap(&mut self) {
+        self.wrap = match self.wrap {
+            Wrap::Off => Wrap::Anywhere,
+            Wrap::Anywhere => Wrap::AtWords,
+            Wrap::AtWords => Wrap::Off,
+        };
+        self.offset.x = 0;
+        let state = match self.wrap {
+            Wrap::Off => "off",
+            Wrap::Anywhere => "on",
+            Wrap::AtWords => "on, at word boundaries",
+        };
+        self.status_message = StatusMessage::from(format!("Soft wrap {state}."));
+    }
+
+    fn next_theme(&mut self) {
+        self.theme = self.theme.next();
+        self.status_message = StatusMessage::from(format!("Theme: {}", self.theme.name()));
+    }
+
+    fn insert_char(&mut self, c: char) {
+        self.document.insert(&self.cursor_position, c);
+        self.move_cursor(Key::Right);
+    }
+
+    fn indent(&mut self) {
+        let indentation = self.document.indentation(&self.cursor_position);
+        // inserting can't fail
+        let _ = Command::Insert(indentation).run(&mut self.document, &mut self.cursor_position);
+    }
+
+    fn delete_backward(&mut self) {
+        if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+            self.move_cursor(Key::Left);
+            self.document.delete(&self.cursor_position);
+        }
+    }
+
+    fn delete_forward(&mut self) {
+        self.document.delete(&self.cursor_position);
+    }
+
+    fn paste(&mut self, text: &str) {
+        self.end_pending_keys();
+        // line breaks and tabs are the only control characters worth keeping
+        let text: String = text.chars().filter(|c| !c.is_control() || *c == '\n' || *c == '\t').collect();
+        self.selection_anchor = None;
+        // inserting can't fail
+        let _ = Command::Insert(text).run(&mut self.document, &mut self.cursor_position);
+        self.last_edit = Instant::now();
+        self.scroll();
     }
 
     fn move_cursor(&mut self, pressed_key: Key) {
+        if self.wrap != Wrap::Off && matches!(pressed_key, Key::Up | Key::Down) {
+            self.move_screen_line(pressed_key);
+            return;
+        }
+
         let Position { mut x, mut y } = self.cursor_position;
         let terminal_height = self.terminal.size().height as usize;
         let height = self.document.len();
         let mut width = if let Some(row) = self.document.get_row(y) {
             row.len()
         } else { 0 };
+        let (column, _) = self.cursor_columns();
 
         match pressed_key {
             Key::Up => y = y.saturating_sub(1),
@@ -253,6 +773,13 @@ impl Editor {
             _ => ()
         }
 
+        // going up or down stays in the same screen column, whatever the graphemes before it are
+        if matches!(pressed_key, Key::Up | Key::Down | Key::PageUp | Key::PageDown) {
+            if let Some(row) = self.document.get_row(y) {
+                x = row.index_at_column(column, self.document.tab_width());
+            }
+        }
+
         width = if let Some(row) = self.document.get_row(y) {
             row.len()
         } else { 0 };
@@ -263,29 +790,270 @@ impl Editor {
 
         self.cursor_position = Position { x , y };
     }
-    
-    fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
 
-        if self.should_quit {
-            Terminal::clear_screen();
-            println!("Goodbye.\r");
+    // While wrapping, up and down go by screen line and keep to the same column on the screen.
+    fn move_screen_line(&mut self, pressed_key: Key) {
+        let y = self.cursor_position.y;
+        let line = self.cursor_line();
+        let column = self.cursor_columns().0.saturating_sub(self.line_column(y, line));
+        let (y, line) = if pressed_key == Key::Up {
+            if line > 0 {
+                (y, line.saturating_sub(1))
+            } else if y > 0 {
+                (y.saturating_sub(1), self.screen_lines(y.saturating_sub(1)).len().saturating_sub(1))
+            } else {
+                return;
+            }
+        } else if line.saturating_add(1) < self.screen_lines(y).len() {
+            (y, line.saturating_add(1))
+        } else if y < self.document.len() {
+            (y.saturating_add(1), 0)
         } else {
-            self.document.highlight(
-                &self.highlighted_word, 
-                Some(self.offset.y.saturating_add(self.terminal.size().height as usize)));
-            self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+            return;
+        };
+
+        let x = self.index_on_line(y, line, column);
+        self.cursor_position = Position { x, y };
+    }
+
+    // The grapheme at `column` of row `y` screen line `line`, counting from the start of the line.
+    fn index_on_line(&self, y: usize, line: usize, column: usize) -> usize {
+        let tab_width = self.document.tab_width();
+        let lines = self.screen_lines(y);
+        self.document.get_row(y).map_or(0, |row| {
+            let x = row.index_at_column(self.line_column(y, line).saturating_add(column), tab_width);
+            // a column past the end of the line would land at the start of the next one
+            lines.get(line.saturating_add(1)).map_or(x, |next| cmp::min(x, next.saturating_sub(1)))
+        })
+    }
+
+    // The document position shown at screen column `x` of screen line `y`. Anything below the
+    // last row is taken to mean the end of the document.
+    fn position_at(&self, x: usize, y: usize) -> Position {
+        let column = x.saturating_sub(self.gutter_width());
+        let (index, x) = if self.wrap == Wrap::Off {
+            let index = self.offset.y.saturating_add(y);
+            let x = self.document.get_row(index).map_or(0, |row| {
+                row.index_at_column(self.offset.x.saturating_add(column), self.document.tab_width())
             });
+            (index, x)
+        } else {
+            let (mut index, mut line) = (self.offset.y, self.offset.x);
+            for _ in 0..y {
+                if line.saturating_add(1) < self.screen_lines(index).len() {
+                    line = line.saturating_add(1);
+                } else {
+                    index = index.saturating_add(1);
+                    line = 0;
+                }
+            }
+            (index, self.index_on_line(index, line, column))
+        };
+
+        if index < self.document.len() {
+            return Position { x, y: index };
+        }
+        let y = self.document.len().saturating_sub(1);
+        Position {
+            x: self.document.get_row(y).map_or(0, Row::len),
+            y,
+        }
+    }
+
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        self.end_pending_keys();
+        let height = self.terminal.size().height as usize;
+        match event {
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => self.scroll_view(WHEEL_LINES, true),
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => self.scroll_view(WHEEL_LINES, false),
+            MouseEvent::Press(MouseButton::Left, x, y) => {
+                // termion counts from 1
+                let at = (usize::from(x).saturating_sub(1), usize::from(y).saturating_sub(1));
+                if at.1 >= height {
+                    return;
+                }
+                let now = Instant::now();
+                let clicks = match self.last_click {
+                    Some((time, last_at, clicks)) if last_at == at && now.duration_since(time) < DOUBLE_CLICK_TIME => {
+                        clicks % 3 + 1
+                    }
+                    _ => 1,
+                };
+                self.last_click = Some((now, at, clicks));
+
+                let position = self.position_at(at.0, at.1);
+                let y = position.y;
+                let words = match (clicks, self.document.get_row(y)) {
+                    (1, _) | (_, None) => position.x..position.x,
+                    (2, Some(row)) => row.word_at(position.x),
+                    (_, Some(row)) => 0..row.len(),
+                };
+                self.selection_anchor = Some(Position { x: words.start, y });
+                self.cursor_position = Position { x: words.end, y };
+                self.scroll();
+            },
+            MouseEvent::Hold(x, y) => {
+                if self.selection_anchor.is_none() {
+                    return;
+                }
+                // dragging past the edge of the text area still selects up to it
+                let y = cmp::min(usize::from(y).saturating_sub(1), height.saturating_sub(1));
+                self.cursor_position = self.position_at(usize::from(x).saturating_sub(1), y);
+                self.scroll();
+            },
+            // a click that didn't turn into a drag leaves nothing selected
+            MouseEvent::Release(_, _) if self.selection().is_none() => self.selection_anchor = None,
+            _ => (),
+        }
+    }
+
+    // Moves the view by `lines` screen lines, taking the cursor along if it would end up off the screen.
+    fn scroll_view(&mut self, lines: usize, up: bool) {
+        let last_row = self.document.len().saturating_sub(1);
+        for _ in 0..lines {
+            if self.wrap == Wrap::Off {
+                self.offset.y = if up {
+                    self.offset.y.saturating_sub(1)
+                } else {
+                    cmp::min(self.offset.y.saturating_add(1), last_row)
+                };
+            } else if up {
+                if self.offset.x > 0 {
+                    self.offset.x = self.offset.x.saturating_sub(1);
+                } else if self.offset.y > 0 {
+                    self.offset.y = self.offset.y.saturating_sub(1);
+                    self.offset.x = self.screen_lines(self.offset.y).len().saturating_sub(1);
+                }
+            } else if self.offset.x.saturating_add(1) < self.screen_lines(self.offset.y).len() {
+                self.offset.x = self.offset.x.saturating_add(1);
+            } else if self.offset.y < last_row {
+                self.offset.y = self.offset.y.saturating_add(1);
+                self.offset.x = 0;
+            }
         }
 
-        Terminal::cursor_show();
-        Terminal::flush()
+        let height = self.terminal.size().height as usize;
+        let on_screen = self.cursor_on_screen();
+        let above = if self.wrap == Wrap::Off {
+            self.cursor_position.y < self.offset.y
+        } else {
+            (self.cursor_position.y, self.cursor_line()) < (self.offset.y, self.offset.x)
+        };
+        // the cursor only goes along to stay on the screen, which isn't picking a new end for the selection
+        if above {
+            self.selection_anchor = None;
+            self.cursor_position = self.position_at(on_screen.x, 0);
+        } else if on_screen.y >= height {
+            self.selection_anchor = None;
+            self.cursor_position = self.position_at(on_screen.x, height.saturating_sub(1));
+        }
+    }
+
+    // The selection from its start to its end, whichever way it was made.
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor.as_ref()?;
+        let cursor = &self.cursor_position;
+        match (anchor.y, anchor.x).cmp(&(cursor.y, cursor.x)) {
+            cmp::Ordering::Less => Some((anchor.clone(), cursor.clone())),
+            cmp::Ordering::Greater => Some((cursor.clone(), anchor.clone())),
+            cmp::Ordering::Equal => None,
+        }
+    }
+
+    // Grapheme indexes the screen lines of row `y` start at, which is just the one without wrapping.
+    fn screen_lines(&self, y: usize) -> Vec<usize> {
+        match (self.wrap, self.document.get_row(y)) {
+            (Wrap::Off, _) | (_, None) => vec![0],
+            (wrap, Some(row)) => row.wrap(self.text_width(), self.document.tab_width(), wrap == Wrap::AtWords),
+        }
+    }
+
+    // Which of its row's screen lines the cursor is on.
+    fn cursor_line(&self) -> usize {
+        let x = self.cursor_position.x;
+        self.screen_lines(self.cursor_position.y).iter().rposition(|start| *start <= x).unwrap_or(0)
+    }
+
+    // The column in row `y` screen line `line` starts at.
+    fn line_column(&self, y: usize, line: usize) -> usize {
+        let start = self.screen_lines(y).get(line).copied().unwrap_or(0);
+        self.document.get_row(y).map_or(0, |row| row.column(start, self.document.tab_width()))
+    }
+
+    // Line numbers go in a gutter, and so does the mark for where a wrapped row goes on.
+    fn gutter_width(&self) -> usize {
+        if self.line_numbers {
+            let digits = self.document.len().max(1).to_string().len();
+            digits.saturating_add(1)
+        } else if self.wrap == Wrap::Off {
+            0
+        } else {
+            2
+        }
+    }
+
+    // `line` is the screen line of row `index` that goes on screen line `y`.
+    fn draw_gutter(&self, screen: &mut Screen, y: usize, index: usize, line: usize) {
+        let width = self.gutter_width();
+        if width == 0 {
+            return;
+        }
+        let label = if line > 0 {
+            "\u{21aa}".to_string()
+        } else if self.line_numbers {
+            index.saturating_add(1).to_string()
+        } else {
+            String::new()
+        };
+        let label_width = width.saturating_sub(1);
+        screen.put_str(0, y, &format!("{label:>label_width$} "), *self.theme.gutter());
+    }
+
+    fn text_width(&self) -> usize {
+        (self.terminal.size().width as usize).saturating_sub(self.gutter_width())
+    }
+    
+    fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
+        if self.should_quit {
+            return Ok(());
+        }
+
+        self.document.highlight(
+            self.highlighted_word.as_deref(),
+            Some(self.offset.y.saturating_add(self.terminal.size().height as usize)));
+        let width = self.terminal.size().width as usize;
+        let height = self.terminal.size().height as usize;
+        let mut screen = Screen::new(width, height.saturating_add(2));
+        self.draw_rows(&mut screen);
+        self.draw_palette(&mut screen);
+        self.draw_status_bar(&mut screen);
+        self.draw_message_bar(&mut screen);
+        let cursor = self.cursor_on_screen();
+        self.terminal.draw(screen, &cursor)
+    }
+
+    fn cursor_on_screen(&self) -> Position {
+        let Position { y, .. } = self.cursor_position;
+        let column = self.cursor_columns().0;
+        // the line after the last row is a plain `~` without a gutter
+        let gutter_width = if y < self.document.len() { self.gutter_width() } else { 0 };
+        if self.wrap == Wrap::Off {
+            return Position {
+                x: column.saturating_sub(self.offset.x).saturating_add(gutter_width),
+                y: y.saturating_sub(self.offset.y),
+            };
+        }
+
+        let line = self.cursor_line();
+        let lines_above: usize = (self.offset.y..y).map(|index| self.screen_lines(index).len()).sum();
+        Position {
+            // the end of a row that fills its last line exactly is just off the screen
+            x: cmp::min(
+                column.saturating_sub(self.line_column(y, line)),
+                self.text_width().saturating_sub(1),
+            ).saturating_add(gutter_width),
+            y: lines_above.saturating_add(line).saturating_sub(self.offset.x),
+        }
     }
     
     fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
@@ -296,7 +1064,15 @@ impl Editor {
             self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
             self.refresh_screen()?;
 
-            let key = Terminal::read_key()?;
+            let key = match self.read_input()? {
+                Event::Key(key) => key,
+                Event::Paste(text) => {
+                    // a prompt is a single line, so only the first one of a paste goes in
+                    result.extend(text.chars().take_while(|c| *c != '\n').filter(|c| !c.is_control()));
+                    Key::Null
+                },
+                _ => continue,
+            };
 
             match key {
                 Key::Char('\n') => {
@@ -330,32 +1106,79 @@ impl Editor {
         Ok(Some(result))
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    // Draws the part of `row` in screen `columns` onto screen line `y`.
+    fn draw_row(&self, screen: &mut Screen, y: usize, row: &Row, columns: Range<usize>, marked: &[usize], selected: &Range<usize>) {
+        let gutter_width = self.gutter_width();
+        let tab_width = self.document.tab_width();
+        for (x, cell) in row.render(columns.start, columns.end, &self.theme, marked, selected, tab_width).into_iter().enumerate() {
+            screen.set(x.saturating_add(gutter_width), y, cell);
+        }
     }
 
     #[allow(clippy::integer_arithmetic, clippy::integer_division)]
-    fn draw_rows(&self) {
+    fn draw_rows(&self, screen: &mut Screen) {
         let height = self.terminal.size().height;
+        let mut brackets = Vec::new();
+        // this runs for every frame, so the search can't go all the way to the top of a long file
+        let rows = self.offset.y.saturating_sub(BRACKET_SCAN_ROWS)
+            ..self.offset.y.saturating_add(height as usize).saturating_add(BRACKET_SCAN_ROWS);
+        if let Some(partner) = self.document.matching_bracket_within(&self.cursor_position, rows) {
+            brackets.push(self.cursor_position.clone());
+            brackets.push(partner);
+        }
 
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self.document.get_row(self.offset.y.saturating_add(terminal_row as usize)) {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height/3 {
-                self.print_welcome_message();
+        let selection = self.selection();
+
+        let height = height as usize;
+        let text_width = self.text_width();
+        let tab_width = self.document.tab_width();
+        let mut y = 0;
+        let mut index = self.offset.y;
+        while y < height {
+            if let Some(row) = self.document.get_row(index) {
+                let marked: Vec<usize> = brackets.iter().filter(|position| position.y == index).map(|position| position.x).collect();
+                let selected = match &selection {
+                    Some((start, end)) if start.y <= index && index <= end.y => {
+                        let from = if index == start.y { start.x } else { 0 };
+                        let to = if index == end.y { end.x } else { usize::MAX };
+                        from..to
+                    },
+                    _ => 0..0,
+                };
+                if self.wrap == Wrap::Off {
+                    let columns = self.offset.x..self.offset.x.saturating_add(text_width);
+                    self.draw_gutter(screen, y, index, 0);
+                    self.draw_row(screen, y, row, columns, &marked, &selected);
+                    y += 1;
+                } else {
+                    let lines = self.screen_lines(index);
+                    let skipped = if index == self.offset.y { self.offset.x } else { 0 };
+                    for (line, line_start) in lines.iter().enumerate().skip(skipped) {
+                        if y >= height {
+                            break;
+                        }
+                        let start = row.column(*line_start, tab_width);
+                        let end = lines.get(line + 1).map_or(usize::MAX, |next| row.column(*next, tab_width));
+                        self.draw_gutter(screen, y, index, line);
+                        let columns = start..cmp::min(end, start.saturating_add(text_width));
+                        self.draw_row(screen, y, row, columns, &marked, &selected);
+                        y += 1;
+                    }
+                }
             } else {
-                println!("~\r");
+                if self.document.is_empty() && y == height/3 {
+                    self.print_welcome_message(screen, y);
+                } else {
+                    screen.put_str(0, y, "~", Style::default());
+                }
+                y += 1;
             }
+            index += 1;
         }
     }
 
-    fn print_welcome_message(&self) {
-        let mut welcome_message = format!("Welcome to Hecto v{}.\r", VERSION);
+    fn print_welcome_message(&self, screen: &mut Screen, y: usize) {
+        let mut welcome_message = format!("Welcome to Hecto v{}.", VERSION);
         let width =self.terminal.size().width as usize;
         #[allow(clippy::integer_arithmetic, clippy::integer_division)]
         let padding = width.saturating_sub(welcome_message.len())/2; 
@@ -363,14 +1186,37 @@ impl Editor {
         welcome_message = format!("~{}{}", spaces, welcome_message);
         welcome_message.truncate(width);
 
-        println!("{}\r", welcome_message);
+        screen.put_str(0, y, &welcome_message, Style::default());
+    }
+
+    fn handle_resize(&mut self) {
+        // a bigger window may have room for what was scrolled away, a smaller one is dealt with by scroll
+        let height = self.terminal.size().height as usize;
+        let width = self.text_width();
+        let y = self.cursor_position.y;
+        if self.wrap == Wrap::Off {
+            let (_, next_column) = self.cursor_columns();
+            self.offset.x = cmp::min(self.offset.x, next_column.saturating_sub(width));
+            self.offset.y = cmp::min(self.offset.y, y.saturating_sub(height.saturating_sub(1)));
+        } else {
+            // the top row may wrap onto fewer lines now
+            let lines = self.screen_lines(self.offset.y).len();
+            self.offset.x = cmp::min(self.offset.x, lines.saturating_sub(1));
+        }
+        self.scroll();
     }
 
     fn scroll(&mut self) {
-        let Position {x, y} = self.cursor_position;
+        if self.wrap != Wrap::Off {
+            self.scroll_wrapped();
+            return;
+        }
+
+        let y = self.cursor_position.y;
+        let (column, next_column) = self.cursor_columns();
+        let width = self.text_width();
         let offset = &mut self.offset;
         let height = self.terminal.size().height as usize;
-        let width = self.terminal.size().width as usize;
 
         if y < offset.y {
             // if you scroll up, update offset to the new reduced y value
@@ -380,16 +1226,78 @@ impl Editor {
             // ex: if y is one line out of screen, offset.y increases by 1.
             offset.y = y.saturating_sub(height).saturating_add(1); 
         }
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        // offset.x is in screen columns, and the whole of a wide grapheme under the cursor has to fit
+        if column < offset.x {
+            offset.x = column;
+        } else if next_column > offset.x.saturating_add(width) {
+            offset.x = next_column.saturating_sub(width);
         }
     }
+
+    // Like `scroll`, but counting screen lines rather than rows.
+    fn scroll_wrapped(&mut self) {
+        let height = self.terminal.size().height as usize;
+        let y = self.cursor_position.y;
+        let line = self.cursor_line();
+        if (y, line) < (self.offset.y, self.offset.x) {
+            self.offset = Position { x: line, y };
+            return;
+        }
+
+        // walk up from the cursor to the lowest the top of the screen can be with the cursor still on it
+        let (mut top, mut top_line, mut remaining) = (y, line, height.saturating_sub(1));
+        while remaining > 0 {
+            if top_line >= remaining {
+                top_line = top_line.saturating_sub(remaining);
+                break;
+            }
+            remaining = remaining.saturating_sub(top_line.saturating_add(1));
+            if top == 0 {
+                top_line = 0;
+                break;
+            }
+            top = top.saturating_sub(1);
+            top_line = self.screen_lines(top).len().saturating_sub(1);
+        }
+        if (self.offset.y, self.offset.x) < (top, top_line) {
+            self.offset = Position { x: top_line, y: top };
+        }
+    }
+
+    // The screen columns the cursor's grapheme starts in and ends before, from the start of the row.
+    fn cursor_columns(&self) -> (usize, usize) {
+        let Position { x, y } = self.cursor_position;
+        let tab_width = self.document.tab_width();
+        self.document.get_row(y).map_or((0, 1), |row| {
+            let column = row.column(x, tab_width);
+            (column, cmp::max(row.column(x.saturating_add(1), tab_width), column.saturating_add(1)))
+        })
+    }
     
-    fn draw_status_bar(&self) {
+    // The palette's matches go over the bottom of the text, each with the keys bound to it.
+    fn draw_palette(&self, screen: &mut Screen) {
+        let Some(palette) = &self.palette else {
+            return;
+        };
+        let height = self.terminal.size().height as usize;
+        let width = self.terminal.size().width as usize;
+        let lines = cmp::min(cmp::min(palette.matches.len(), PALETTE_LINES), height);
+        let first = palette.selected.saturating_sub(lines.saturating_sub(1));
+        let top = height.saturating_sub(lines);
+        for (line, (index, command)) in palette.matches.iter().enumerate().skip(first).take(lines).enumerate() {
+            let y = top.saturating_add(line);
+            let style = if index == palette.selected { *self.theme.selection() } else { *self.theme.message_bar() };
+            let bindings = self.config.keymap().bindings(command.name).join(", ");
+            let description = format!(" {} ({})", command.description, command.name);
+            screen.fill(y, style);
+            screen.put_str(0, y, &description, style);
+            screen.put_str(width.saturating_sub(bindings.len().saturating_add(1)), y, &bindings, style);
+        }
+    }
+
+    fn draw_status_bar(&self, screen: &mut Screen) {
         let mut status;
-        let mut file_name = "[No file name]".to_string();
+        let mut file_name = if self.filter { "[stdin]" } else { "[No file name]" }.to_string();
         let width = self.terminal.size().width as usize;
         let modified_message = if self.document.is_dirty() {
             "(modified)"
@@ -410,27 +1318,86 @@ impl Editor {
         status.push_str(&" ".repeat(width.saturating_sub(status_len)));
         status = format!("{}{}", status, line_indicator);
         status.truncate(width);
-        Terminal::set_bg_color(STATUS_COLOR);
-        Terminal::set_fg_color(FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_bg_color();
-        Terminal::reset_fg_color();
+        let y = self.terminal.size().height as usize;
+        screen.fill(y, *self.theme.status_bar());
+        screen.put_str(0, y, &status, *self.theme.status_bar());
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+    fn draw_message_bar(&self, screen: &mut Screen) {
         let status = &self.status_message;
-        if Instant::now() - status.time < Duration::new(5, 0) {
+        if status.time.elapsed() < self.config.message_timeout() {
             let mut text = status.text.clone();
             text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+            let y = (self.terminal.size().height as usize).saturating_add(1);
+            screen.put_str(0, y, &text, *self.theme.message_bar());
         }
     }
 
 }
 
 fn die(e: std::io::Error) {
-    Terminal::clear_screen();
     panic!("{}", e);
 }
 
+// The commands whose name or description has the letters of `query` in order, best match first.
+// With nothing typed yet, that is all of them but the palette itself.
+fn matching_commands(query: &str) -> Vec<&'static EditorCommand> {
+    let mut scored: Vec<(usize, &'static EditorCommand)> = COMMANDS.iter().filter(|command| command.name != "command-palette").filter_map(|command| {
+        let score = [command.description, command.name].iter().filter_map(|text| fuzzy_score(query, text)).min()?;
+        Some((score, command))
+    }).collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, command)| command).collect()
+}
+
+// Lower is better. Each letter is taken where it first turns up after the one before it, and
+// costs nothing when it comes right after that one, or when it starts a word, however many
+// letters that skips. Any other costs one, plus one for each letter skipped to get to it. None if
+// the letters aren't all there.
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0_usize;
+    let mut position = 0_usize;
+    for (index, wanted) in query.to_lowercase().chars().filter(|c| !c.is_whitespace()).enumerate() {
+        let found = position.saturating_add(text.get(position..)?.iter().position(|c| *c == wanted)?);
+        let word_start = found == 0 || text.get(found.saturating_sub(1)).is_some_and(|c| !c.is_alphanumeric());
+        let adjacent = index > 0 && found == position;
+        if !word_start && !adjacent {
+            score = score.saturating_add(found.saturating_sub(position)).saturating_add(1);
+        }
+        position = found.saturating_add(1);
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(query: &str) -> Vec<&'static str> {
+        matching_commands(query).iter().map(|command| command.name).collect()
+    }
+
+    #[test]
+    fn fuzzy_scores() {
+        assert_eq!(fuzzy_score("save", "save"), Some(0));
+        assert_eq!(fuzzy_score("lnu", "line-numbers"), Some(6));
+        assert_eq!(fuzzy_score("tl", "turn line numbers on"), Some(0));
+        assert_eq!(fuzzy_score("le", "line"), Some(3));
+        assert_eq!(fuzzy_score("ie", "line"), Some(4));
+        assert_eq!(fuzzy_score("Go Line", "go-to-line"), Some(0));
+        assert_eq!(fuzzy_score("sv", "save"), Some(2));
+        assert_eq!(fuzzy_score("xyz", "save"), None);
+        assert_eq!(fuzzy_score("", "save"), Some(0));
+    }
+
+    #[test]
+    fn the_best_match_comes_first() {
+        assert_eq!(names("save").first(), Some(&"save"));
+        assert_eq!(names("line num").first(), Some(&"line-numbers"));
+        assert_eq!(names("bracket")[..2], ["matching-bracket", "rainbow-brackets"]);
+        assert_eq!(names("autosave"), ["autosave"]);
+        assert!(!names("").contains(&"command-palette"));
+        assert!(names("qqq").is_empty());
+    }
+}
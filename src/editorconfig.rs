@@ -0,0 +1,538 @@
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = ".editorconfig";
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16_BOM: u16 = 0xfeff;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Latin1,
+    Utf8,
+    Utf8Bom,
+    Utf16Be,
+    Utf16Le,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IndentSize {
+    Columns(usize),
+    Tab, // the same as the tab width
+}
+
+/// What the `.editorconfig` files above a file say about it, with `None` for whatever they leave
+/// out. See <https://editorconfig.org> for what the properties mean.
+#[derive(Default, Clone)]
+pub struct EditorConfig {
+    indent_style: Option<IndentStyle>,
+    indent_size: Option<IndentSize>,
+    tab_width: Option<usize>,
+    end_of_line: Option<LineEnding>,
+    charset: Option<Charset>,
+    trim_trailing_whitespace: Option<bool>,
+    insert_final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    /// Reads the `.editorconfig` files from the directory of `path` upwards, up to the first one
+    /// with `root = true`. Nearer files win over those further up, and later sections over
+    /// earlier ones. Files that can't be read are skipped.
+    pub fn for_file(path: &Path) -> Self {
+        let Some(path) = absolute(path) else {
+            return Self::default();
+        };
+        let mut files = Vec::new();
+        for directory in path.ancestors().skip(1) {
+            let Ok(text) = fs::read_to_string(directory.join(FILE_NAME)) else {
+                continue;
+            };
+            let root = is_root(&text);
+            files.push((directory.to_path_buf(), text));
+            if root {
+                break;
+            }
+        }
+
+        let mut config = Self::default();
+        for (directory, text) in files.iter().rev() {
+            let Ok(relative) = path.strip_prefix(directory) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            config.read(text, &relative);
+        }
+        config
+    }
+
+    /// What applies to a file in the current directory that has no name yet. Only sections that
+    /// match whatever the name, like `[*]`, do.
+    pub fn for_unnamed_file() -> Self {
+        Self::for_file(Path::new("*"))
+    }
+
+    // Takes on the properties of every section of `text` that matches `relative`, the path of the
+    // file from the directory the `.editorconfig` is in.
+    fn read(&mut self, text: &str, relative: &str) {
+        let mut matching = false;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[') {
+                let glob = section.rfind(']').map_or(section, |end| section.get(..end).unwrap_or_default());
+                matching = matches_section(glob, relative);
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                continue;
+            };
+            if matching {
+                self.set(&name.trim().to_lowercase(), &value.trim().to_lowercase());
+            }
+        }
+    }
+
+    // Unknown properties and values are ignored, as the format asks. `unset` goes back to the default.
+    fn set(&mut self, name: &str, value: &str) {
+        let unset = value == "unset";
+        let flag = match value {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match name {
+            "indent_style" => {
+                self.indent_style = match value {
+                    "tab" => Some(IndentStyle::Tab),
+                    "space" => Some(IndentStyle::Space),
+                    _ if unset => None,
+                    _ => self.indent_style,
+                };
+            },
+            "indent_size" => {
+                self.indent_size = match value {
+                    "tab" => Some(IndentSize::Tab),
+                    _ if unset => None,
+                    _ => value.parse().ok().filter(|size| *size > 0).map(IndentSize::Columns).or(self.indent_size),
+                };
+            },
+            "tab_width" => {
+                self.tab_width = if unset {
+                    None
+                } else {
+                    value.parse().ok().filter(|width| *width > 0).or(self.tab_width)
+                };
+            },
+            "end_of_line" => {
+                self.end_of_line = match value {
+                    "lf" => Some(LineEnding::Lf),
+                    "crlf" => Some(LineEnding::CrLf),
+                    "cr" => Some(LineEnding::Cr),
+                    _ if unset => None,
+                    _ => self.end_of_line,
+                };
+            },
+            "charset" => {
+                self.charset = match value {
+                    "latin1" => Some(Charset::Latin1),
+                    "utf-8" => Some(Charset::Utf8),
+                    "utf-8-bom" => Some(Charset::Utf8Bom),
+                    "utf-16be" => Some(Charset::Utf16Be),
+                    "utf-16le" => Some(Charset::Utf16Le),
+                    _ if unset => None,
+                    _ => self.charset,
+                };
+            },
+            "trim_trailing_whitespace" if unset => self.trim_trailing_whitespace = None,
+            "trim_trailing_whitespace" => self.trim_trailing_whitespace = flag.or(self.trim_trailing_whitespace),
+            "insert_final_newline" if unset => self.insert_final_newline = None,
+            "insert_final_newline" => self.insert_final_newline = flag.or(self.insert_final_newline),
+            _ => (),
+        }
+    }
+
+    /// `tab_width`, or else a numeric `indent_size`, which it defaults to.
+    pub fn tab_width(&self) -> Option<usize> {
+        self.tab_width.or(match self.indent_size {
+            Some(IndentSize::Columns(size)) => Some(size),
+            _ => None,
+        })
+    }
+
+    /// How many columns one level of indentation takes when indenting with spaces, given the
+    /// tab width in use.
+    pub fn indent_size(&self, tab_width: usize) -> usize {
+        match self.indent_size {
+            Some(IndentSize::Columns(size)) => size,
+            _ => tab_width,
+        }
+    }
+
+    pub fn indent_style(&self) -> Option<IndentStyle> {
+        self.indent_style
+    }
+
+    pub fn trim_trailing_whitespace(&self) -> bool {
+        self.trim_trailing_whitespace == Some(true)
+    }
+
+    /// Without a setting, files end in a line break, which is what hecto has always done.
+    pub fn insert_final_newline(&self) -> bool {
+        self.insert_final_newline != Some(false)
+    }
+
+    pub fn line_ending(&self) -> &'static str {
+        match self.end_of_line {
+            Some(LineEnding::CrLf) => "\r\n",
+            Some(LineEnding::Cr) => "\r",
+            Some(LineEnding::Lf) | None => "\n",
+        }
+    }
+
+    /// Splits text read from a file into lines, dropping the line breaks.
+    pub fn lines<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        if self.end_of_line == Some(LineEnding::Cr) {
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            if text.is_empty() {
+                return Vec::new();
+            }
+            text.split('\r').collect()
+        } else {
+            text.lines().collect()
+        }
+    }
+
+    /// Turns the bytes of a file into text, going by `charset`.
+    ///
+    /// # Errors
+    ///
+    /// If a file that should be UTF-8 isn't.
+    pub fn decode(&self, bytes: Vec<u8>) -> Result<String, Error> {
+        let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+            let units = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]])).skip_while(|unit| *unit == UTF16_BOM);
+            char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+        };
+        match self.charset {
+            Some(Charset::Latin1) => Ok(bytes.into_iter().map(char::from).collect()),
+            Some(Charset::Utf16Be) => Ok(utf16(&bytes, u16::from_be_bytes)),
+            Some(Charset::Utf16Le) => Ok(utf16(&bytes, u16::from_le_bytes)),
+            Some(Charset::Utf8Bom | Charset::Utf8) | None => {
+                let bytes = match bytes.strip_prefix(UTF8_BOM) {
+                    Some(rest) if self.charset == Some(Charset::Utf8Bom) => rest.to_vec(),
+                    _ => bytes,
+                };
+                String::from_utf8(bytes).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+            },
+        }
+    }
+
+    /// Turns text into the bytes to write to the file, going by `charset`. Characters that
+    /// Latin-1 doesn't have become question marks.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self.charset {
+            Some(Charset::Latin1) => text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect(),
+            Some(Charset::Utf16Be) => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Some(Charset::Utf16Le) => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Some(Charset::Utf8Bom) => [UTF8_BOM, text.as_bytes()].concat(),
+            Some(Charset::Utf8) | None => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+fn absolute(path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        return Some(path.to_path_buf());
+    }
+    env::current_dir().ok().map(|directory| directory.join(path))
+}
+
+// `root = true` has to come before the first section.
+fn is_root(text: &str) -> bool {
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            return false;
+        }
+        if let Some((name, value)) = line.split_once('=') {
+            if name.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true") {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// A section without a slash is about file names in any directory, one with a slash about paths
+// from the directory the `.editorconfig` is in.
+fn matches_section(glob: &str, relative: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    if glob.contains(&'/') {
+        let glob = glob.strip_prefix(&['/']).unwrap_or(&glob);
+        let path: Vec<char> = relative.chars().collect();
+        matches(glob, &path)
+    } else {
+        let name: Vec<char> = relative.rsplit('/').next().unwrap_or(relative).chars().collect();
+        matches(&glob, &name)
+    }
+}
+
+// Glob matching the way EditorConfig does it: `*` is anything but a slash, `**` is anything,
+// `?` one character, `[abc]` and `[!abc]` a character that is or isn't one of those, `{a,b}`
+// either one and `{1..10}` a number in that range. A backslash takes the next character as it is.
+#[allow(clippy::indexing_slicing)]
+fn matches(glob: &[char], text: &[char]) -> bool {
+    let Some(&first) = glob.first() else {
+        return text.is_empty();
+    };
+    let rest = &glob[1..];
+    match first {
+        '*' if rest.first() == Some(&'*') => {
+            let rest = &rest[1..];
+            (0..=text.len()).any(|skipped| matches(rest, &text[skipped..]))
+        },
+        '*' => {
+            let end = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=end).any(|skipped| matches(rest, &text[skipped..]))
+        },
+        '?' => text.first().is_some_and(|c| *c != '/') && matches(rest, &text[1..]),
+        '[' => match class(rest) {
+            Some((negated, members, after)) => text.first().is_some_and(|c| {
+                *c != '/' && members.contains(c) != negated && matches(after, &text[1..])
+            }),
+            None => text.first() == Some(&'[') && matches(rest, &text[1..]),
+        },
+        '{' => match braces(rest) {
+            Some((Braces::Range(low, high), after)) => {
+                let digits = text.iter().enumerate().take_while(|(index, c)| c.is_ascii_digit() || (*index == 0 && **c == '-')).count();
+                (1..=digits).any(|length| {
+                    let number: String = text[..length].iter().collect();
+                    number.parse::<i64>().is_ok_and(|number| low <= number && number <= high) && matches(after, &text[length..])
+                })
+            },
+            Some((Braces::Choices(choices), after)) => choices.iter().any(|choice| {
+                let mut glob = choice.clone();
+                glob.extend_from_slice(after);
+                matches(&glob, text)
+            }),
+            None => text.first() == Some(&'{') && matches(rest, &text[1..]),
+        },
+        '\\' if !rest.is_empty() => text.first() == rest.first() && matches(&rest[1..], &text[1..]),
+        c => text.first() == Some(&c) && matches(rest, &text[1..]),
+    }
+}
+
+// The characters of a `[...]` class, `glob` starting right after the `[`, and what comes after it.
+#[allow(clippy::indexing_slicing)]
+fn class(glob: &[char]) -> Option<(bool, Vec<char>, &[char])> {
+    let negated = glob.first() == Some(&'!');
+    let glob = if negated { &glob[1..] } else { glob };
+    let end = glob.iter().position(|c| *c == ']')?;
+    let mut members = Vec::new();
+    let mut index = 0;
+    while index < end {
+        let c = glob[index];
+        // a range such as a-z
+        if glob.get(index.saturating_add(1)) == Some(&'-') && index.saturating_add(2) < end {
+            members.extend(c..=glob[index.saturating_add(2)]);
+            index = index.saturating_add(3);
+        } else {
+            members.push(c);
+            index = index.saturating_add(1);
+        }
+    }
+    Some((negated, members, &glob[end.saturating_add(1)..]))
+}
+
+enum Braces {
+    Range(i64, i64),
+    Choices(Vec<Vec<char>>),
+}
+
+// What a `{...}` stands for, `glob` starting right after the `{`, and what comes after it.
+#[allow(clippy::indexing_slicing)]
+fn braces(glob: &[char]) -> Option<(Braces, &[char])> {
+    let mut depth = 0_usize;
+    let mut choices = vec![Vec::new()];
+    let mut escaped = false;
+    for (index, c) in glob.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if *c == '\\' {
+            escaped = true;
+        } else if *c == '{' {
+            depth = depth.saturating_add(1);
+        } else if *c == '}' && depth > 0 {
+            depth = depth.saturating_sub(1);
+        } else if *c == '}' {
+            let after = &glob[index.saturating_add(1)..];
+            let inside: String = glob[..index].iter().collect();
+            if let Some((low, high)) = inside.split_once("..") {
+                if let (Ok(low), Ok(high)) = (low.parse(), high.parse()) {
+                    return Some((Braces::Range(low, high), after));
+                }
+            }
+            // a single choice isn't an alternative at all, so the braces are taken literally
+            if choices.len() < 2 {
+                return None;
+            }
+            return Some((Braces::Choices(choices), after));
+        } else if *c == ',' && depth == 0 {
+            choices.push(Vec::new());
+            continue;
+        }
+        if let Some(choice) = choices.last_mut() {
+            choice.push(*c);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Document;
+    use std::process;
+
+    fn matching(glob: &str, path: &str) -> bool {
+        matches_section(glob, path)
+    }
+
+    fn read(text: &str, path: &str) -> EditorConfig {
+        let mut config = EditorConfig::default();
+        config.read(text, path);
+        config
+    }
+
+    // A directory of its own under the temporary one, with `.editorconfig` files in it as given.
+    fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("hecto-editorconfig-{}-{name}", process::id()));
+        for (path, text) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn stars() {
+        assert!(matching("*", "src/main.rs"));
+        assert!(matching("*.rs", "src/main.rs"));
+        assert!(!matching("*.rs", "src/main.rsx"));
+        assert!(matching("src/*.rs", "src/main.rs"));
+        assert!(!matching("src/*.rs", "src/editor/main.rs"));
+        assert!(matching("src/**.rs", "src/editor/main.rs"));
+        assert!(matching("**/main.rs", "src/editor/main.rs"));
+        assert!(!matching("/main.rs", "src/main.rs"));
+        assert!(matching("/src/main.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(matching("*.{rs,toml}", "Cargo.toml"));
+        assert!(matching("*.{rs,toml}", "main.rs"));
+        assert!(!matching("*.{rs,toml}", "main.c"));
+        assert!(matching("{Makefile,*.mk}", "rules.mk"));
+        assert!(matching("{single}", "{single}"));
+        assert!(matching("file{1..3}.txt", "file2.txt"));
+        assert!(matching("file{1..3}.txt", "file3.txt"));
+        assert!(!matching("file{1..3}.txt", "file4.txt"));
+        assert!(!matching("file{1..3}.txt", "file12.txt"));
+        assert!(matching("{-5..5}", "-3"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matching("[abc].txt", "b.txt"));
+        assert!(!matching("[abc].txt", "d.txt"));
+        assert!(matching("[!x].txt", "y.txt"));
+        assert!(!matching("[!x].txt", "x.txt"));
+        assert!(matching("[a-c]?", "bz"));
+        assert!(matching("\\*", "*"));
+        assert!(!matching("\\*", "a"));
+    }
+
+    #[test]
+    fn later_sections_win_and_unset_goes_back_to_the_default() {
+        let text = "[*]\nindent_style = tab\ntab_width = 8\ntrim_trailing_whitespace = true\n\n[*.rs]\nindent_style = space\nindent_size = 4\ntab_width = unset\ntrim_trailing_whitespace = unset\n";
+        let rust = read(text, "main.rs");
+        assert!(rust.indent_style() == Some(IndentStyle::Space));
+        assert_eq!(rust.tab_width(), Some(4));
+        assert!(!rust.trim_trailing_whitespace());
+        let other = read(text, "notes.txt");
+        assert!(other.indent_style() == Some(IndentStyle::Tab));
+        assert_eq!(other.tab_width(), Some(8));
+        assert!(other.trim_trailing_whitespace());
+    }
+
+    #[test]
+    fn unknown_values_are_ignored() {
+        let config = read("[*]\nindent_style = tab\nindent_style = sideways\ntab_width = 0\nend_of_line = LF\n", "a");
+        assert!(config.indent_style() == Some(IndentStyle::Tab));
+        assert_eq!(config.tab_width(), None);
+        assert_eq!(config.line_ending(), "\n");
+    }
+
+    #[test]
+    fn the_search_upwards_stops_at_the_root() {
+        let directory = directory("root", &[
+            (".editorconfig", "[*]\nindent_style = tab\ntab_width = 3\n"),
+            ("inner/.editorconfig", "root = true\n[*]\nindent_size = 2\n"),
+            ("inner/deeper/.editorconfig", "[*.rs]\nindent_style = space\n"),
+        ]);
+        let inner = EditorConfig::for_file(&directory.join("inner/deeper/main.rs"));
+        assert!(inner.indent_style() == Some(IndentStyle::Space));
+        assert_eq!(inner.tab_width(), Some(2));
+        let outer = EditorConfig::for_file(&directory.join("main.rs"));
+        assert!(outer.indent_style() == Some(IndentStyle::Tab));
+        assert_eq!(outer.tab_width(), Some(3));
+        assert!(!is_root("[*]\nroot = true\n"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn crlf_files_are_saved_as_they_were_read() {
+        let directory = directory("crlf", &[(".editorconfig", "[*]\nend_of_line = crlf\ntrim_trailing_whitespace = true\n")]);
+        let path = directory.join("text.txt");
+        fs::write(&path, "one  \r\ntwo\r\n\r\nthree\t\r\n").unwrap();
+        let mut document = Document::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(document.len(), 4);
+        document.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"one\r\ntwo\r\n\r\nthree\r\n");
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn latin1_files_are_saved_as_they_were_read() {
+        let directory = directory("latin1", &[(".editorconfig", "[*.txt]\ncharset = latin1\n")]);
+        let path = directory.join("text.txt");
+        fs::write(&path, b"caf\xe9 \xb5\n").unwrap();
+        let mut document = Document::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(document.get_row(0).unwrap().as_bytes(), "café µ".as_bytes());
+        document.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"caf\xe9 \xb5\n");
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn latin1_has_no_room_for_other_characters() {
+        let config = read("[*]\ncharset = latin1\n", "a");
+        assert_eq!(config.encode("é€"), b"\xe9?");
+    }
+}
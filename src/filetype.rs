@@ -1,12 +1,23 @@
+use std::path::Path;
+use tree_sitter::Language;
+
+/// What kind of file a document is, and so how it gets highlighted and indented.
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    grammar: Option<Language>,
+    tab_width: usize, // columns between tab stops
 }
 
 #[derive(Default)]
 pub struct HighlightingOptions {
     number: bool,
+    number_prefixes: bool,
+    number_separators: bool,
+    number_exponents: bool,
+    number_suffixes: Vec<String>,
     strings: bool,
+    prefixed_strings: bool, // b"..", r"..", br".." and r#".."#
     chars: bool,
     comments: bool,
     primary_keywords: Vec<String>,
@@ -19,6 +30,8 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            grammar: None,
+            tab_width: 8,
         }
     }
 }
@@ -28,13 +41,34 @@ impl FileType {
         return self.name.clone();
     }
 
+    #[allow(clippy::too_many_lines)]
     pub fn from(file_name: &str) -> Self {
         if file_name.ends_with(".rs") {
             return Self {
                 name: String::from("Rust"),
                 hl_opts: HighlightingOptions {
                     number: true,
+                    number_prefixes: true,
+                    number_separators: true,
+                    number_exponents: true,
+                    number_suffixes: vec![
+                        "i8".to_string(),
+                        "i16".to_string(),
+                        "i32".to_string(),
+                        "i64".to_string(),
+                        "i128".to_string(),
+                        "isize".to_string(),
+                        "u8".to_string(),
+                        "u16".to_string(),
+                        "u32".to_string(),
+                        "u64".to_string(),
+                        "u128".to_string(),
+                        "usize".to_string(),
+                        "f32".to_string(),
+                        "f64".to_string(),
+                    ],
                     strings: true,
+                    prefixed_strings: true,
                     chars: true,
                     comments: true,
                     primary_keywords: vec![
@@ -108,6 +142,49 @@ impl FileType {
                     ],
                     multiline_comments: true,
                 },
+                grammar: Some(tree_sitter_rust::LANGUAGE.into()),
+                tab_width: 4,
+            };
+        }
+
+        if file_name.ends_with(".go") {
+            let words = |words: &[&str]| words.iter().map(ToString::to_string).collect();
+            return Self {
+                name: String::from("Go"),
+                hl_opts: HighlightingOptions {
+                    number: true,
+                    number_prefixes: true,
+                    number_separators: true,
+                    number_exponents: true,
+                    strings: true,
+                    chars: true,
+                    comments: true,
+                    primary_keywords: words(&[
+                        "break", "case", "chan", "const", "continue", "default", "defer", "else",
+                        "fallthrough", "for", "func", "go", "goto", "if", "import", "interface", "map",
+                        "package", "range", "return", "select", "struct", "switch", "type", "var",
+                        "true", "false", "nil", "iota",
+                    ]),
+                    secondary_keywords: words(&[
+                        "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int",
+                        "int8", "int16", "int32", "int64", "rune", "string", "uint", "uint8", "uint16",
+                        "uint32", "uint64", "uintptr", "any",
+                    ]),
+                    multiline_comments: true,
+                    ..HighlightingOptions::default()
+                },
+                grammar: None,
+                tab_width: 8,
+            };
+        }
+
+        // recipes have to be indented with tabs, which are traditionally eight columns
+        let base_name = Path::new(file_name).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if base_name == "Makefile" || base_name == "makefile" || base_name == "GNUmakefile" || base_name.ends_with(".mk") {
+            return Self {
+                name: String::from("Makefile"),
+                tab_width: 8,
+                ..Self::default()
             };
         }
 
@@ -117,6 +194,14 @@ impl FileType {
     pub fn highlighting_options(&self) -> &HighlightingOptions {
         &self.hl_opts
     }
+
+    pub fn grammar(&self) -> Option<&Language> {
+        self.grammar.as_ref()
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
 }
 
 impl HighlightingOptions {
@@ -124,10 +209,30 @@ impl HighlightingOptions {
         self.number
     }
 
+    pub fn number_prefixes(&self) -> bool {
+        self.number_prefixes
+    }
+
+    pub fn number_separators(&self) -> bool {
+        self.number_separators
+    }
+
+    pub fn number_exponents(&self) -> bool {
+        self.number_exponents
+    }
+
+    pub fn number_suffixes(&self) -> &Vec<String> {
+        &self.number_suffixes
+    }
+
     pub fn strings(&self) -> bool {
         self.strings
     }
 
+    pub fn prefixed_strings(&self) -> bool {
+        self.prefixed_strings
+    }
+
     pub fn chars(&self) -> bool {
         self.chars
     }
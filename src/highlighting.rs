@@ -1,6 +1,4 @@
-use termion::color;
-
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Type {
     None,
     Number,
@@ -11,19 +9,15 @@ pub enum Type {
     PrimaryKeywords,
     SecondaryKeywords,
     MultiLineComment,
+    Bracket(usize), // nesting depth, for rainbow brackets
 }
 
-impl Type {
-    pub fn to_color(self) -> impl color::Color {
-        match self {
-            Type::Number => color::Rgb(220, 163, 163),
-            Type::Match => color::Rgb(38, 139, 210),
-            Type::String => color::Rgb(211, 54, 130),
-            Type::Character => color::Rgb(108, 113, 196),
-            Type::Comment | Type::MultiLineComment => color::Rgb(133, 153, 0),
-            Type::PrimaryKeywords => color::Rgb(181, 137, 0),            
-            Type::SecondaryKeywords => color::Rgb(42, 161, 152),
-            _ => color::Rgb(255, 255, 255),
-        }
-    }
-}
\ No newline at end of file
+/// What a row was still inside of when it ended, so the next row can pick up from there.
+#[derive(PartialEq, Clone, Copy, Default)]
+pub enum State {
+    #[default]
+    None,
+    MultiLineComment,
+    String,
+    RawString(usize), // number of '#' needed to close it
+}
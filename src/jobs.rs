@@ -0,0 +1,56 @@
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use crate::Editor;
+
+// What a job hands back to be applied on the editor's thread once it is done.
+pub type Completion = Box<dyn FnOnce(&mut Editor) + Send>;
+
+// Work running on its own thread. Finishing wakes up the event loop through `waker`,
+// which then collects the completions with `finished`.
+pub struct Jobs {
+    sender: Sender<Completion>,
+    receiver: Receiver<Completion>,
+    waker: UnixStream,
+    running: usize,
+}
+
+impl Jobs {
+    pub fn new(waker: UnixStream) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            waker,
+            running: 0,
+        }
+    }
+
+    pub fn spawn<F>(&mut self, job: F)
+    where
+        F: FnOnce() -> Completion + Send + 'static,
+    {
+        let sender = self.sender.clone();
+        let waker = self.waker.try_clone();
+        thread::spawn(move || {
+            // if the editor is gone by now there is nobody left to tell
+            if sender.send(job()).is_ok() {
+                if let Ok(mut waker) = waker {
+                    let _ = waker.write_all(&[0]);
+                }
+            }
+        });
+        self.running = self.running.saturating_add(1);
+    }
+
+    pub fn finished(&mut self) -> Vec<Completion> {
+        let completions: Vec<Completion> = self.receiver.try_iter().collect();
+        self.running = self.running.saturating_sub(completions.len());
+        completions
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running > 0
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use termion::event::Key;
+
+/// What a sequence of keys amounts to.
+pub enum Lookup<'a> {
+    /// The name of the command the keys are bound to.
+    Command(&'a str),
+    /// The keys start one or more longer bindings, so more have to come.
+    Prefix,
+    Unbound,
+}
+
+/// Which key sequences run which commands. A sequence is usually a single key, or a chord such
+/// as Ctrl-K Ctrl-N.
+#[derive(Clone)]
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let defaults = [
+            ("ctrl-p", "command-palette"),
+            ("ctrl-q", "quit"),
+            ("ctrl-s", "save"),
+            ("ctrl-f", "find"),
+            ("ctrl-g", "go-to-line"),
+            ("ctrl-b", "matching-bracket"),
+            ("ctrl-r", "rainbow-brackets"),
+            ("ctrl-k ctrl-n", "line-numbers"),
+            ("ctrl-w", "wrap"),
+            ("ctrl-t", "theme"),
+            ("up", "cursor-up"),
+            ("down", "cursor-down"),
+            ("left", "cursor-left"),
+            ("right", "cursor-right"),
+            ("home", "line-start"),
+            ("end", "line-end"),
+            ("pageup", "page-up"),
+            ("pagedown", "page-down"),
+            ("enter", "newline"),
+            ("tab", "indent"),
+            ("backspace", "delete-backward"),
+            ("delete", "delete-forward"),
+        ];
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for (keys, command) in defaults {
+            keymap.bind(parse_keys(keys).unwrap_or_default(), command);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Binds `keys` to `command`. A binding that `keys` starts, or that starts `keys`, couldn't
+    /// be reached anymore, so it goes.
+    pub fn bind(&mut self, keys: Vec<Key>, command: &str) {
+        self.bindings.retain(|bound, _| !bound.starts_with(&keys) && !keys.starts_with(bound));
+        self.bindings.insert(keys, command.to_string());
+    }
+
+    pub fn unbind(&mut self, keys: &[Key]) {
+        self.bindings.remove(keys);
+    }
+
+    /// What `keys`, the ones pressed so far, are bound to.
+    pub fn lookup(&self, keys: &[Key]) -> Lookup<'_> {
+        if let Some(command) = self.bindings.get(keys) {
+            return Lookup::Command(command);
+        }
+        if self.bindings.keys().any(|bound| bound.starts_with(keys)) {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// Every key sequence bound to `command`, as the message bar shows them, shortest first.
+    pub fn bindings(&self, command: &str) -> Vec<String> {
+        let mut bindings: Vec<&Vec<Key>> = self.bindings.iter().filter(|(_, bound)| *bound == command).map(|(keys, _)| keys).collect();
+        bindings.sort_by_key(|keys| (keys.len(), keys_name(keys)));
+        bindings.into_iter().map(|keys| keys_name(keys)).collect()
+    }
+}
+
+/// Reads a key sequence the way the configuration file writes it: keys such as `ctrl-s`,
+/// `alt-x`, `f5`, `pagedown` or `a`, separated by spaces.
+pub fn parse_keys(text: &str) -> Option<Vec<Key>> {
+    let keys: Option<Vec<Key>> = text.split_whitespace().map(parse_key).collect();
+    keys.filter(|keys| !keys.is_empty())
+}
+
+fn parse_key(text: &str) -> Option<Key> {
+    let text = text.to_lowercase();
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    if let Some(rest) = text.strip_prefix("ctrl-") {
+        return single(rest).filter(char::is_ascii_alphanumeric).map(Key::Ctrl);
+    }
+    if let Some(rest) = text.strip_prefix("alt-") {
+        return single(rest).map(Key::Alt);
+    }
+    if let Some(number) = text.strip_prefix('f').and_then(|number| number.parse::<u8>().ok()) {
+        return (1..=12).contains(&number).then_some(Key::F(number));
+    }
+    match text.as_str() {
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "insert" => Some(Key::Insert),
+        "delete" => Some(Key::Delete),
+        "backspace" => Some(Key::Backspace),
+        "esc" => Some(Key::Esc),
+        "enter" => Some(Key::Char('\n')),
+        "tab" => Some(Key::Char('\t')),
+        "space" => Some(Key::Char(' ')),
+        _ => single(&text).map(Key::Char),
+    }
+}
+
+/// How a key sequence is shown in messages, such as `Ctrl-K Ctrl-N`.
+pub fn keys_name(keys: &[Key]) -> String {
+    let names: Vec<String> = keys.iter().map(|key| key_name(*key)).collect();
+    names.join(" ")
+}
+
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+        Key::Alt(c) => format!("Alt-{c}"),
+        Key::F(number) => format!("F{number}"),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Esc => "Esc".to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        parse_keys(text).unwrap()
+    }
+
+    fn command<'a>(keymap: &'a Keymap, text: &str) -> Option<&'a str> {
+        match keymap.lookup(&keys(text)) {
+            Lookup::Command(name) => Some(name),
+            Lookup::Prefix | Lookup::Unbound => None,
+        }
+    }
+
+    #[test]
+    fn parsing_and_naming() {
+        assert_eq!(keys("ctrl-K ctrl-n"), [Key::Ctrl('k'), Key::Ctrl('n')]);
+        assert_eq!(keys("alt-x f5 space enter a"), [Key::Alt('x'), Key::F(5), Key::Char(' '), Key::Char('\n'), Key::Char('a')]);
+        for wrong in ["", "ctrl-", "ctrl-ab", "ctrl-%", "f13", "hyper-x", "ab"] {
+            assert!(parse_keys(wrong).is_none(), "{wrong:?}");
+        }
+        assert_eq!(keys_name(&keys("ctrl-k ctrl-n")), "Ctrl-K Ctrl-N");
+        assert_eq!(keys_name(&keys("space tab x")), "Space Tab x");
+    }
+
+    #[test]
+    fn lookup() {
+        let keymap = Keymap::default();
+        assert_eq!(command(&keymap, "ctrl-s"), Some("save"));
+        assert_eq!(command(&keymap, "ctrl-k ctrl-n"), Some("line-numbers"));
+        assert!(matches!(keymap.lookup(&keys("ctrl-k")), Lookup::Prefix));
+        assert!(matches!(keymap.lookup(&keys("ctrl-k x")), Lookup::Unbound));
+        assert!(matches!(keymap.lookup(&keys("x")), Lookup::Unbound));
+    }
+
+    #[test]
+    fn binding_evicts_what_it_would_hide() {
+        let mut keymap = Keymap::default();
+        // a single key can't also start a sequence
+        keymap.bind(keys("ctrl-k"), "wrap");
+        assert_eq!(command(&keymap, "ctrl-k"), Some("wrap"));
+        assert!(keymap.bindings("line-numbers").is_empty());
+
+        // and a sequence that starts with a bound key takes its place
+        keymap.bind(keys("ctrl-s ctrl-s"), "save");
+        assert!(matches!(keymap.lookup(&keys("ctrl-s")), Lookup::Prefix));
+        assert_eq!(keymap.bindings("save"), ["Ctrl-S Ctrl-S"]);
+    }
+
+    #[test]
+    fn unbinding_and_listing() {
+        let mut keymap = Keymap::default();
+        keymap.bind(keys("f2"), "save");
+        keymap.bind(keys("ctrl-x ctrl-s"), "save");
+        assert_eq!(keymap.bindings("save"), ["Ctrl-S", "F2", "Ctrl-X Ctrl-S"]);
+        keymap.unbind(&keys("ctrl-s"));
+        assert!(matches!(keymap.lookup(&keys("ctrl-s")), Lookup::Unbound));
+        // a prefix isn't a binding to take away
+        keymap.unbind(&keys("ctrl-x"));
+        assert_eq!(keymap.bindings("save"), ["F2", "Ctrl-X Ctrl-S"]);
+    }
+}
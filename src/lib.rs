@@ -0,0 +1,52 @@
+//! The editing core of hecto, a small terminal text editor.
+//!
+//! [`Document`] holds a file as [`Row`]s and does the loading, saving, editing, searching and
+//! syntax highlighting. [`Editor`] is the terminal interface on top of it, drawing through a
+//! [`Backend`]: the real [`Terminal`], or a [`VirtualTerminal`] that keeps everything in memory.
+//!
+//! ```no_run
+//! use hecto::{Document, Position, SearchDirection};
+//!
+//! let mut document = Document::open("src/main.rs")?;
+//! document.insert_str(&Position { x: 0, y: 0 }, "// hello\n");
+//! let found = document.find("fn main", &Position::default(), SearchDirection::Forward);
+//! document.highlight(None, None);
+//! document.save()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+#![warn(clippy::all, clippy::pedantic, clippy::restriction)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::implicit_return,
+    clippy::shadow_reuse,
+    clippy::print_stdout,
+    clippy::print_stderr,
+    clippy::wildcard_enum_match_arm,
+    clippy::else_if_without_else
+)]
+mod editor;
+mod terminal;
+mod row;
+mod document;
+pub mod highlighting;
+mod filetype;
+mod theme;
+mod syntax;
+mod jobs;
+mod screen;
+mod virtual_terminal;
+mod command;
+mod config;
+mod editorconfig;
+mod keymap;
+pub use terminal::{Backend, ColorSupport, Event, Size, Terminal};
+pub use virtual_terminal::VirtualTerminal;
+pub use editor::{Editor, EditorCommand, Position, SearchDirection, Wrap};
+pub use row::Row;
+pub use document::Document;
+pub use filetype::{FileType, HighlightingOptions};
+pub use theme::{Style, Theme};
+pub use screen::{Cell, Screen};
+pub use command::{run_script, Command, ScriptError};
+pub use config::{Config, FileSettings};
+pub use keymap::{Keymap, Lookup};
@@ -2,25 +2,91 @@
 #![allow(
     clippy::missing_docs_in_private_items,
     clippy::implicit_return,
-    clippy::shadow_reuse,
-    clippy::print_stdout,
-    clippy::wildcard_enum_match_arm,
-    clippy::else_if_without_else
 )]
-mod editor;
-mod terminal;
-mod row;
-mod document;
-mod highlighting;
-mod filetype;
-use editor::Editor;
-pub use terminal::Terminal;
-pub use editor::Position;
-pub use row::Row;
-pub use document::Document;
-pub use editor::SearchDirection;
-pub use filetype::FileType;
+use hecto::{run_script, Config, Document, Editor, ScriptError, Terminal};
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
 
 fn main() {
-    Editor::default().run();
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--script") {
+        process::exit(script(args.get(2..).unwrap_or_default()));
+    }
+
+    let mut editor = if args.get(1).map(String::as_str) == Some("-") {
+        filter()
+    } else {
+        Editor::default()
+    };
+    // the panic hook has restored the terminal by the time this gets to run, and with the input
+    // closed there is nobody left to ask about unsaved changes
+    if !matches!(panic::catch_unwind(AssertUnwindSafe(|| editor.run())), Ok(Ok(()))) {
+        editor.emergency_save();
+        process::exit(1);
+    }
+
+    // `cmd | hecto - | cmd` passes on what was saved, and quitting without saving breaks the pipeline
+    if editor.is_filter() {
+        let Some(output) = editor.into_output() else {
+            process::exit(1);
+        };
+        if let Err(error) = io::stdout().write_all(&output) {
+            eprintln!("hecto: can't write to stdout: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+// `hecto -` edits what comes in on stdin, which has to be read before the terminal takes over.
+fn filter() -> Editor {
+    let mut input = Vec::new();
+    if let Err(error) = io::stdin().read_to_end(&mut input) {
+        eprintln!("hecto: can't read stdin: {error}");
+        process::exit(1);
+    }
+    let document = Document::unnamed(&input);
+    let terminal = Terminal::default().expect("Failed to initialize terminal.");
+    Editor::with_document(Box::new(terminal), document, Config::load())
+}
+
+// `hecto --script commands.txt file` edits the file without a terminal. The exit status is 0 when
+// every command ran, 1 when one of them failed and 2 when the script or the file couldn't be used.
+fn script(args: &[String]) -> i32 {
+    let [script_name, file_name] = args else {
+        eprintln!("usage: hecto --script <commands> <file>");
+        return 2;
+    };
+    let script = match fs::read_to_string(script_name) {
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("hecto: can't read {script_name}: {error}");
+            return 2;
+        },
+    };
+    let mut document = match Document::open(file_name) {
+        Ok(document) => document,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            let mut document = Document::default();
+            document.file_name = Some(file_name.clone());
+            document
+        },
+        Err(error) => {
+            eprintln!("hecto: can't open {file_name}: {error}");
+            return 2;
+        },
+    };
+
+    match run_script(&script, &mut document) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("{script_name}: {error}");
+            match error {
+                ScriptError::Invalid(..) => 2,
+                ScriptError::Failed(..) => 1,
+            }
+        },
+    }
 }
@@ -1,15 +1,23 @@
 use std::cmp;
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
-use termion::color;
+use unicode_width::UnicodeWidthStr;
 use crate::filetype::HighlightingOptions;
 use crate::{Position, SearchDirection};
 use crate::highlighting;
+use crate::Theme;
+use crate::screen::Cell;
 
+/// One line of a document, without its line break. Indexes into it count graphemes.
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlighting: Vec<highlighting::Type>,
-    pub is_highlighted: bool,
+    pub(crate) is_highlighted: bool,
+    start_state: highlighting::State,
+    end_state: highlighting::State,
+    start_depth: Option<usize>, // bracket depth the rainbow pass started with, if it ran
+    end_depth: usize,
     len: usize,
 }
 
@@ -19,42 +27,142 @@ impl From<&str> for Row {
             string: String::from(content),
             highlighting: Vec::new(),
             is_highlighted: false,
+            start_state: highlighting::State::None,
+            end_state: highlighting::State::None,
+            start_depth: None,
+            end_depth: 0,
             len: content.graphemes(true).count(),
         }
     }
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
-        let mut current_highlighting = &highlighting::Type::None;
-        let mut result = String::new();
-        #[allow(clippy::integer_arithmetic)]
-        for (index, grapheme) in self.string[..]
-                .graphemes(true)
-                .enumerate()
-                .skip(start)
-                .take(end-start) {
-                        if let Some(c) = grapheme.chars().next() {
-                        let highlighting_type = self.highlighting.get(index).unwrap_or( &highlighting::Type::None);
-
-                        if current_highlighting != highlighting_type {
-                            current_highlighting = highlighting_type;
-                            let highlight_start = format!("{}", color::Fg(highlighting_type.to_color()));
-                            result.push_str(&highlight_start[..]);
-                        }
-                    
-                        if c == '\t' {
-                            result.push_str(" ");
-                        } else {
-                            result.push(c);
-                        }
+    // `start` and `end` are screen columns. `marked` are the indexes of matching brackets and `selected`
+    // the ones inside the selection, which get drawn in their own styles. A wide grapheme cut in half
+    // by either edge shows up as blanks.
+    pub(crate) fn render(
+        &self,
+        start: usize,
+        end: usize,
+        theme: &Theme,
+        marked: &[usize],
+        selected: &Range<usize>,
+        tab_width: usize,
+    ) -> Vec<Cell> {
+        let mut cells = Vec::new();
+        let mut column: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
+            let next_column = column.saturating_add(grapheme_width(grapheme, column, tab_width));
+            if next_column > start {
+                let highlighting_type = self.highlighting.get(index).unwrap_or( &highlighting::Type::None);
+                let style = if selected.contains(&index) {
+                    theme.selection()
+                } else if marked.contains(&index) {
+                    theme.matching_bracket()
+                } else {
+                    theme.highlight(*highlighting_type)
+                };
+
+                if column < start || next_column > end || grapheme == "\t" {
+                    for _ in cmp::max(column, start)..cmp::min(next_column, end) {
+                        cells.push(Cell {
+                            grapheme: " ".to_string(),
+                            style: *style,
+                        });
+                    }
+                } else {
+                    cells.push(Cell {
+                        grapheme: displayed(grapheme),
+                        style: *style,
+                    });
+                    // the terminal draws the rest of a wide grapheme by itself
+                    for _ in column.saturating_add(1)..next_column {
+                        cells.push(Cell {
+                            grapheme: String::new(),
+                            style: *style,
+                        });
                     }
                 }
-        let highlight_end = format!("{}", color::Fg(color::Reset));
-        result.push_str(&highlight_end[..]);
-        result
+            }
+            column = next_column;
+        }
+        cells
+    }
+
+    // Where each screen line starts when the row is wrapped at `width` columns, as grapheme indexes.
+    // With `at_words` a line is broken after the last whitespace that still fits, if there is one.
+    pub(crate) fn wrap(&self, width: usize, tab_width: usize, at_words: bool) -> Vec<usize> {
+        let width = cmp::max(width, 1);
+        let mut starts = vec![0];
+        let mut columns = Vec::new();
+        let mut line_start: usize = 0;
+        let mut line_start_column: usize = 0;
+        let mut last_space: Option<usize> = None;
+        let mut column: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            columns.push(column);
+            let next_column = column.saturating_add(grapheme_width(grapheme, column, tab_width));
+            if next_column.saturating_sub(line_start_column) > width && index > line_start {
+                line_start = match last_space {
+                    Some(space) if at_words && space >= line_start => space.saturating_add(1),
+                    _ => index,
+                };
+                line_start_column = columns.get(line_start).copied().unwrap_or(column);
+                if next_column.saturating_sub(line_start_column) > width {
+                    line_start = index;
+                    line_start_column = column;
+                }
+                starts.push(line_start);
+                last_space = None;
+            }
+            if grapheme.chars().all(char::is_whitespace) {
+                last_space = Some(index);
+            }
+            column = next_column;
+        }
+        starts
+    }
+
+    /// The graphemes around `at` of the same kind as it: a word, a stretch of whitespace, or
+    /// a run of anything else.
+    pub fn word_at(&self, at: usize) -> Range<usize> {
+        let kinds: Vec<u8> = self.string[..]
+            .graphemes(true)
+            .map(|grapheme| match grapheme.chars().next() {
+                Some(c) if c.is_alphanumeric() || c == '_' => 0,
+                Some(c) if c.is_whitespace() => 1,
+                _ => 2,
+            })
+            .collect();
+        let Some(kind) = kinds.get(at) else {
+            return at..at;
+        };
+        let start = kinds.iter().take(at).rposition(|other| other != kind).map_or(0, |before| before.saturating_add(1));
+        let end = kinds.iter().skip(at).position(|other| other != kind).map_or(kinds.len(), |after| at.saturating_add(after));
+        start..end
+    }
+
+    /// The screen column the grapheme at `at` starts in, counting from the start of the row.
+    pub fn column(&self, at: usize, tab_width: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(at)
+            .fold(0, |column, grapheme| column.saturating_add(grapheme_width(grapheme, column, tab_width)))
+    }
+
+    /// The grapheme covering `column`, or the end of the row if it is shorter than that.
+    pub fn index_at_column(&self, column: usize, tab_width: usize) -> usize {
+        let mut start: usize = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            start = start.saturating_add(grapheme_width(grapheme, start, tab_width));
+            if start > column {
+                return index;
+            }
+        }
+        self.len
     }
 
     pub fn len(&self) -> usize {
@@ -65,7 +173,17 @@ impl Row {
         self.len == 0
     }
 
-    pub fn insert(&mut self, at: usize, c: char) {
+    // The state the last highlighting ended in, for the next row to pick up from.
+    pub(crate) fn end_state(&self) -> highlighting::State {
+        self.end_state
+    }
+
+    pub(crate) fn end_depth(&self) -> usize {
+        self.end_depth
+    }
+
+    // Inserts `c` before the grapheme at `at`, or at the end if `at` is past it.
+    pub(crate) fn insert(&mut self, at: usize, c: char) {
         if at >= self.len() {
             self.string.push(c);
             self.len += 1;
@@ -87,8 +205,9 @@ impl Row {
         self.string = result;
     }
 
+    // Removes the grapheme at `at`, if there is one.
     #[allow(clippy::integer_arithmetic)]
-    pub fn delete(&mut self, at: usize) {
+    pub(crate) fn delete(&mut self, at: usize) {
         if at >= self.len() {
             return; // do nothing
         }
@@ -106,12 +225,14 @@ impl Row {
         self.string = result;
     }
 
-    pub fn append(&mut self, string_to_add: Row) {
+    // Adds the text of another row to the end of this one.
+    pub(crate) fn append(&mut self, string_to_add: Row) {
         self.string = format!("{}{}", self.string, string_to_add.string);
-        self.len = self.string.len();
+        self.len = self.string[..].graphemes(true).count();
     }
 
-    pub fn split(&mut self, at: usize) -> Self {
+    // Cuts the row in two at `at`, keeping the start and returning the rest.
+    pub(crate) fn split(&mut self, at: usize) -> Self {
         let mut row = String::new();
         let mut splitted_str = String::new();
         let mut length = 0;
@@ -134,14 +255,80 @@ impl Row {
             string: splitted_str,
             highlighting: Vec::new(),
             is_highlighted: false,
+            start_state: highlighting::State::None,
+            end_state: highlighting::State::None,
+            start_depth: None,
+            end_depth: 0,
             len: split_len,
         }
     }
 
+    /// Brackets outside of strings and comments, as (index, bracket) pairs.
+    /// Only meaningful once the row has been highlighted.
+    pub fn brackets(&self) -> Vec<(usize, char)> {
+        let mut brackets = Vec::new();
+        let mut char_index = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if let Some(c) = grapheme.chars().next() {
+                let in_code = self.highlighting.get(char_index).is_none_or(|hl_type| {
+                    matches!(hl_type, highlighting::Type::None | highlighting::Type::Bracket(_))
+                });
+                if in_code && matches!(c, '(' | ')' | '[' | ']' | '{' | '}') {
+                    brackets.push((index, c));
+                }
+            }
+            char_index += grapheme.chars().count();
+        }
+        brackets
+    }
+
+    // Colors the brackets in code by how deeply they're nested, returns the depth the row ends at.
+    pub(crate) fn highlight_brackets(&mut self, start_depth: usize) -> usize {
+        if self.start_depth == Some(start_depth) {
+            return self.end_depth;
+        }
+
+        let mut depth = start_depth;
+        for (c, hl_type) in self.string.chars().zip(self.highlighting.iter_mut()) {
+            if !matches!(hl_type, highlighting::Type::None | highlighting::Type::Bracket(_)) {
+                continue;
+            }
+            match c {
+                '(' | '[' | '{' => {
+                    *hl_type = highlighting::Type::Bracket(depth);
+                    depth = depth.saturating_add(1);
+                }
+                ')' | ']' | '}' => {
+                    depth = depth.saturating_sub(1);
+                    *hl_type = highlighting::Type::Bracket(depth);
+                }
+                _ => (),
+            }
+        }
+
+        self.start_depth = Some(start_depth);
+        self.end_depth = depth;
+        depth
+    }
+
+    /// Where the grapheme at `at` starts in the row's UTF-8 text.
+    pub fn byte_index(&self, at: usize) -> usize {
+        self.string[..]
+            .grapheme_indices(true)
+            .nth(at)
+            .map_or(self.string.len(), |(index, _)| index)
+    }
+
+    /// How each char of the row is highlighted, once it has been.
+    pub fn highlighting(&self) -> &[highlighting::Type] {
+        &self.highlighting
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         return self.string.as_bytes()
     }
 
+    /// The grapheme index `query` shows up at, searching from `at` in `search_direction`.
     pub fn find(&self, query: &str, at: usize, search_direction: SearchDirection) -> Option<usize> {
         if at > self.len || query.is_empty(){
             return None;
@@ -179,7 +366,7 @@ impl Row {
         None
     }
 
-    fn highlight_match(&mut self, word: &Option<String>) {
+    fn highlight_match(&mut self, word: Option<&str>) {
         if let Some(word) = word {
             if word.is_empty() {
                 return;
@@ -240,50 +427,173 @@ impl Row {
         false
     }
 
-    fn highlight_strings(&mut self, index: &mut usize, hl_opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if hl_opts.strings() && c == '"' {
-            loop {
+    fn highlight_strings(&mut self, index: &mut usize, hl_opts: &HighlightingOptions, c: char, chars: &[char], state: &mut highlighting::State) -> bool {
+        if !hl_opts.strings() {
+            return false;
+        }
+
+        if c == '"' {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+            if !self.highlight_string_body(index, chars) {
+                *state = highlighting::State::String;
+            }
+            return true;
+        }
+
+        if hl_opts.prefixed_strings() && (c == 'b' || c == 'r') {
+            if *index > 0 && !is_separator(chars[*index - 1]) {
+                return false;
+            }
+
+            // b"..", r"..", br".." and any number of '#' around raw ones
+            let mut end = *index;
+            if chars.get(end) == Some(&'b') {
+                end += 1;
+            }
+            let is_raw = chars.get(end) == Some(&'r');
+            let mut hashes = 0;
+            if is_raw {
+                end += 1;
+                while chars.get(end) == Some(&'#') {
+                    hashes += 1;
+                    end += 1;
+                }
+            }
+            if end == *index || chars.get(end) != Some(&'"') {
+                return false;
+            }
+
+            for _ in *index..=end {
                 self.highlighting.push(highlighting::Type::String);
                 *index += 1;
-    
-                if let Some (next_char) = chars.get(*index) {
-                    if *next_char == '"' {
-                        break;
-                    }
-                } else {
-                    break;
+            }
+            if is_raw {
+                if !self.highlight_raw_string_body(index, chars, hashes) {
+                    *state = highlighting::State::RawString(hashes);
                 }
+            } else if !self.highlight_string_body(index, chars) {
+                *state = highlighting::State::String;
             }
+            return true;
+        }
+        false
+    }
+
+    // Highlights up to and including the closing quote, skipping over escaped characters.
+    // Returns false if the row ends before the string does.
+    fn highlight_string_body(&mut self, index: &mut usize, chars: &[char]) -> bool {
+        while let Some(c) = chars.get(*index) {
             self.highlighting.push(highlighting::Type::String);
             *index += 1;
-            return true;
+
+            if *c == '\\' {
+                if chars.get(*index).is_some() {
+                    self.highlighting.push(highlighting::Type::String);
+                    *index += 1;
+                }
+            } else if *c == '"' {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn highlight_raw_string_body(&mut self, index: &mut usize, chars: &[char], hashes: usize) -> bool {
+        while let Some(c) = chars.get(*index) {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+
+            if *c == '"' && (0..hashes).all(|offset| chars.get(*index + offset) == Some(&'#')) {
+                for _ in 0..hashes {
+                    self.highlighting.push(highlighting::Type::String);
+                    *index += 1;
+                }
+                return true;
+            }
         }
         false
     }
 
     fn highlight_number(&mut self, index: &mut usize, hl_opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if hl_opts.number() && c.is_ascii_digit() {
-            if *index > 0 {
-                let prev_char = chars[*index - 1]; 
-                if !prev_char.is_ascii_whitespace() && !prev_char.is_ascii_punctuation() {
-                    return false;
+        if !hl_opts.number() || !c.is_ascii_digit() {
+            return false;
+        }
+
+        if *index > 0 {
+            let prev_char = chars[*index - 1];
+            if prev_char == '_' || !is_separator(prev_char) {
+                return false;
+            }
+        }
+
+        let is_digit = |c: char, radix: u32| c.is_digit(radix) || (hl_opts.number_separators() && c == '_');
+        let mut end = *index;
+        let mut radix = 10;
+
+        if hl_opts.number_prefixes() && c == '0' {
+            if let Some(prefix) = chars.get(end + 1) {
+                radix = match prefix {
+                    'x' => 16,
+                    'o' => 8,
+                    'b' => 2,
+                    _ => 10,
+                };
+                if radix != 10 {
+                    end += 2;
                 }
             }
+        }
 
-            loop {
-                self.highlighting.push(highlighting::Type::Number);
-                *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char != '.' && !next_char.is_ascii_digit() {
-                        break;
-                    } 
-                } else {
-                    break;
+        while chars.get(end).is_some_and(|c| is_digit(*c, radix)) {
+            end += 1;
+        }
+        if radix != 10 && !chars[*index + 2..end].iter().any(|c| c.is_digit(radix)) {
+            return false; // a prefix without a digit after it, like `0x` or `0b_`
+        }
+
+        if radix == 10 {
+            // a '.' only belongs to the number if a digit follows, so `1..2` and `1.max(2)` stay apart
+            if chars.get(end) == Some(&'.') && chars.get(end + 1).is_some_and(char::is_ascii_digit) {
+                end += 1;
+                while chars.get(end).is_some_and(|c| is_digit(*c, radix)) {
+                    end += 1;
+                }
+            }
+
+            if hl_opts.number_exponents() && matches!(chars.get(end), Some('e' | 'E')) {
+                let mut exponent_end = end + 1;
+                if matches!(chars.get(exponent_end), Some('+' | '-')) {
+                    exponent_end += 1;
+                }
+                if chars.get(exponent_end).is_some_and(char::is_ascii_digit) {
+                    end = exponent_end;
+                    while chars.get(end).is_some_and(|c| is_digit(*c, radix)) {
+                        end += 1;
+                    }
                 }
             }
-            return true;
         }
-        false
+
+        for suffix in hl_opts.number_suffixes() {
+            let matches_suffix = suffix.chars().enumerate().all(|(offset, c)| chars.get(end + offset) == Some(&c));
+            if matches_suffix {
+                end += suffix.chars().count();
+                break;
+            }
+        }
+
+        if let Some(next_char) = chars.get(end) {
+            if !is_separator(*next_char) || *next_char == '_' {
+                return false; // something like `1st` or `0xZZ`, which is not a number
+            }
+        }
+
+        for _ in *index..end {
+            self.highlighting.push(highlighting::Type::Number);
+            *index += 1;
+        }
+        true
     }
 
     fn highlight_str(&mut self, index: &mut usize, substring: &str, chars: &[char], hl_type: highlighting::Type) -> bool {
@@ -340,68 +650,80 @@ impl Row {
         self.highlight_keywords(index, chars, hl_opts.secondary_keywords(), highlighting::Type::SecondaryKeywords)
     }
 
-    fn highlight_multiline_comments(&mut self, index: &mut usize, hl_opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if hl_opts.comments() && c == '/' && *index < chars.len() {
+    fn highlight_multiline_comments(&mut self, index: &mut usize, hl_opts: &HighlightingOptions, c: char, chars: &[char], state: &mut highlighting::State) -> bool {
+        if hl_opts.multiline_comments() && c == '/' {
             if let Some(next_char) = chars.get(index.saturating_add(1)) {
                 if *next_char == '*' {
-                    let closing_index = 
-                        if let Some(closing_index) = self.string[*index + 2..].find("*/") {
-                            *index + closing_index + 4
-                        } else {
-                            chars.len()
-                        };
-
-                        for _ in *index..closing_index {
-                            self.highlighting.push(highlighting::Type::MultiLineComment);
-                            *index += 1;
-                        }
-                        return true;
+                    for _ in 0..2 {
+                        self.highlighting.push(highlighting::Type::MultiLineComment);
+                        *index += 1;
+                    }
+                    if !self.highlight_multiline_comment_body(index, chars) {
+                        *state = highlighting::State::MultiLineComment;
+                    }
+                    return true;
                 }
-            } 
+            }
         }
         false
     }
- 
-    pub fn highlight(&mut self, hl_opts: &HighlightingOptions, word: &Option<String>, start_with_comment: bool) -> bool {
-        let chars: Vec<char> = self.string.chars().collect();
-        if self.is_highlighted && word.is_none() {
-            if let Some(hl_type) = self.highlighting.last() {
-                if *hl_type == highlighting::Type::MultiLineComment
-                && self.string.len() > 1
-                && self.string[self.string.len() - 2..] == *"*/" {
-                    return true;
-                }
+
+    // Highlights up to and including the closing "*/", returns false if the row ends first.
+    fn highlight_multiline_comment_body(&mut self, index: &mut usize, chars: &[char]) -> bool {
+        while let Some(c) = chars.get(*index) {
+            self.highlighting.push(highlighting::Type::MultiLineComment);
+            *index += 1;
+
+            if *c == '*' && chars.get(*index) == Some(&'/') {
+                self.highlighting.push(highlighting::Type::MultiLineComment);
+                *index += 1;
+                return true;
             }
+        }
+        false
+    }
+ 
+    // For highlighting worked out by something other than the scanner below.
+    pub(crate) fn set_highlighting(&mut self, highlighting: Vec<highlighting::Type>, word: Option<&str>) {
+        self.highlighting = highlighting;
+        self.highlight_match(word);
+        self.start_depth = None;
+        self.start_state = highlighting::State::None;
+        self.end_state = highlighting::State::None;
+        self.is_highlighted = true;
+    }
 
-            return false;
+    // Highlights the row with the plain scanner, starting from the state the previous row ended
+    // in, and returns the state this one ends in.
+    pub(crate) fn highlight(&mut self, hl_opts: &HighlightingOptions, word: Option<&str>, start_state: highlighting::State) -> highlighting::State {
+        // nothing this row depends on has changed, so neither has anything it hands to the next row
+        if self.is_highlighted && self.start_state == start_state {
+            return self.end_state;
         }
+        let chars: Vec<char> = self.string.chars().collect();
         self.highlighting = Vec::new();
         let mut index = 0;
-        let mut in_ml_comment = start_with_comment;
-        if in_ml_comment {
-            let closing_index = if let Some(closing_index) = self.string.find("*/") {
-                closing_index + 2
-            } else {
-                chars.len()
-            };
-            for _ in 0..closing_index {
-                self.highlighting.push(highlighting::Type::MultiLineComment);
-            }
-            index = closing_index;
+        let mut state = highlighting::State::None;
+
+        // finish whatever the previous row left open
+        let closed = match start_state {
+            highlighting::State::None => true,
+            highlighting::State::MultiLineComment => self.highlight_multiline_comment_body(&mut index, &chars),
+            highlighting::State::String => self.highlight_string_body(&mut index, &chars),
+            highlighting::State::RawString(hashes) => self.highlight_raw_string_body(&mut index, &chars, hashes),
+        };
+        if !closed {
+            state = start_state;
         }
 
         while let Some(c) = chars.get(index) {
-            if self.highlight_multiline_comments(&mut index, &hl_opts, *c, &chars) {
-                in_ml_comment = true;
-                continue;
-            }
-            in_ml_comment = false;
-            if self.highlight_char(&mut index, hl_opts, *c, &chars)
+            if self.highlight_multiline_comments(&mut index, hl_opts, *c, &chars, &mut state)
+            || self.highlight_char(&mut index, hl_opts, *c, &chars)
             || self.highlight_comment(&mut index, hl_opts, *c, &chars)
+            || self.highlight_strings(&mut index, hl_opts, *c, &chars, &mut state)
             || self.highlight_primary_keywords(&mut index, &hl_opts, &chars)
             || self.highlight_secondary_keywords(&mut index, &hl_opts, &chars)
-            || self.highlight_number(&mut index, hl_opts, *c, &chars)
-            || self.highlight_strings(&mut index, hl_opts, *c, &chars)  {
+            || self.highlight_number(&mut index, hl_opts, *c, &chars) {
                 continue;
             }
 
@@ -410,14 +732,180 @@ impl Row {
         }
 
         self.highlight_match(word);
-        if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
-            return true;
-        }
+        self.start_depth = None;
+        self.start_state = start_state;
+        self.end_state = state;
         self.is_highlighted = true;
-        false
+        state
+    }
+}
+
+// A tab reaches up to the next tab stop after `column`. Anything the terminal wouldn't move
+// the cursor for still gets a column of its own, so there is something to see and to put the cursor on.
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = cmp::max(tab_width, 1);
+        tab_width.saturating_sub(column.checked_rem(tab_width).unwrap_or_default())
+    } else {
+        cmp::max(grapheme.width(), 1)
+    }
+}
+
+fn displayed(grapheme: &str) -> String {
+    if grapheme.width() == 0 {
+        char::REPLACEMENT_CHARACTER.to_string()
+    } else {
+        grapheme.to_string()
     }
 }
 
 fn is_separator(c: char) -> bool {
     c.is_ascii_whitespace() || c.is_ascii_punctuation()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileType;
+
+    #[test]
+    fn appending_counts_graphemes() {
+        let mut row = Row::from("caf\u{e9}");
+        row.append(Row::from(" \u{1f600}!"));
+        assert_eq!(row.len(), 7);
+        assert_eq!(row.as_bytes(), "caf\u{e9} \u{1f600}!".as_bytes());
+    }
+
+    // The row highlighted as Rust by the plain scanner, one letter for each character, and the
+    // state it ends in.
+    fn scan(text: &str, start_state: highlighting::State) -> (String, highlighting::State) {
+        let mut row = Row::from(text);
+        let end_state = row.highlight(FileType::from("main.rs").highlighting_options(), None, start_state);
+        let kinds = row.highlighting().iter().map(|kind| match kind {
+            highlighting::Type::Number => 'n',
+            highlighting::Type::String => 's',
+            highlighting::Type::Character => 'c',
+            highlighting::Type::Comment | highlighting::Type::MultiLineComment => '/',
+            highlighting::Type::PrimaryKeywords | highlighting::Type::SecondaryKeywords => 'k',
+            highlighting::Type::None | highlighting::Type::Match | highlighting::Type::Bracket(_) => '.',
+        }).collect();
+        (kinds, end_state)
+    }
+
+    fn kinds(text: &str) -> String {
+        scan(text, highlighting::State::None).0
+    }
+
+    #[test]
+    fn escaped_quotes_stay_in_the_string() {
+        assert_eq!(kinds(r#""a\"b" x"#), "ssssss..");
+        assert_eq!(kinds(r#""a\\" x"#), "sssss..");
+    }
+
+    #[test]
+    fn raw_and_byte_strings() {
+        assert_eq!(kinds(r#"r"a\" x"#), "sssss..");
+        assert_eq!(kinds(r##"r#"a"b"# x"##), "ssssssss..");
+        assert_eq!(kinds(r###"r##"a"#b"## x"###), "sssssssssss..");
+        assert_eq!(kinds(r#"b"\x00" x"#), "sssssss..");
+        assert_eq!(kinds(r##"br#"a"# x"##), "sssssss..");
+        // a b or r at the end of a word is just a letter
+        assert_eq!(kinds(r#"ab"c""#), "..sss");
+    }
+
+    #[test]
+    fn strings_carry_over_to_the_next_row() {
+        assert!(scan(r#"x = "abc"#, highlighting::State::None).1 == highlighting::State::String);
+        let (kinds, state) = scan(r#"d\"e" x"#, highlighting::State::String);
+        assert_eq!(kinds, "sssss..");
+        assert!(state == highlighting::State::None);
+
+        assert!(scan(r##"r##"a"#"##, highlighting::State::None).1 == highlighting::State::RawString(2));
+        let (kinds, state) = scan(r###""#b"## x"###, highlighting::State::RawString(2));
+        assert_eq!(kinds, "ssssss..");
+        assert!(state == highlighting::State::None);
+        assert!(scan(r##"a"# x"##, highlighting::State::RawString(2)).1 == highlighting::State::RawString(2));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(kinds("0xFF_u8"), "nnnnnnn");
+        assert_eq!(kinds("0b1010 0o17"), "nnnnnn.nnnn");
+        assert_eq!(kinds("1e-9f64"), "nnnnnnn");
+        assert_eq!(kinds("1_000.5"), "nnnnnnn");
+        assert_eq!(kinds("1..2"), "n..n");
+        assert_eq!(kinds("1.max(2)"), "n.....n.");
+        assert_eq!(kinds("x1"), "..");
+        assert_eq!(kinds("0x 1st"), "......");
+        assert_eq!(kinds("0x_ 0b2"), ".......");
+    }
+
+    #[test]
+    fn wide_and_combining_graphemes() {
+        let row = Row::from("a\u{4e2d}b");
+        assert_eq!((0..=3).map(|at| row.column(at, 4)).collect::<Vec<_>>(), [0, 1, 3, 4]);
+        let row = Row::from("e\u{301}x");
+        assert_eq!(row.len(), 2);
+        assert_eq!((0..=2).map(|at| row.column(at, 4)).collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn columns_inside_a_wide_grapheme_belong_to_it() {
+        let row = Row::from("a\u{4e2d}b");
+        assert_eq!((0..=5).map(|column| row.index_at_column(column, 4)).collect::<Vec<_>>(), [0, 1, 1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn tabs_run_to_the_next_stop() {
+        let row = Row::from("a\tb");
+        assert_eq!((0..=3).map(|at| row.column(at, 4)).collect::<Vec<_>>(), [0, 1, 4, 5]);
+        // a tab that starts one column before a stop is one column wide, one right on a stop a full one
+        assert_eq!(Row::from("abc\tx").column(4, 4), 4);
+        assert_eq!(Row::from("abcd\tx").column(5, 4), 8);
+        assert_eq!(Row::from("a\tb").column(2, 8), 8);
+    }
+
+    #[test]
+    fn columns_inside_a_tab_belong_to_it() {
+        let row = Row::from("a\tb");
+        assert_eq!((0..=5).map(|column| row.index_at_column(column, 4)).collect::<Vec<_>>(), [0, 1, 1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(Row::from("").wrap(10, 4, true), [0]);
+        assert_eq!(Row::from("hello").wrap(5, 4, true), [0]);
+        // at the last space that fits, or wherever the width runs out
+        assert_eq!(Row::from("hello world").wrap(8, 4, true), [0, 6]);
+        assert_eq!(Row::from("hello world").wrap(8, 4, false), [0, 8]);
+        // a word longer than a line is broken up
+        assert_eq!(Row::from("a abcdefghijk").wrap(5, 4, true), [0, 2, 7, 12]);
+        // a wide grapheme that doesn't fit goes to the next line whole, even on a line too narrow for it
+        assert_eq!(Row::from("ab\u{4e2d}").wrap(3, 4, false), [0, 2]);
+        assert_eq!(Row::from("\u{4e2d}\u{4e2d}").wrap(1, 4, false), [0, 1]);
+    }
+
+    // The depth of each bracket in the row, after scanning it as Rust, and the depth it ends at.
+    fn bracket_depths(text: &str, start_depth: usize) -> (Vec<usize>, usize) {
+        let mut row = Row::from(text);
+        row.highlight(FileType::from("main.rs").highlighting_options(), None, highlighting::State::None);
+        let end_depth = row.highlight_brackets(start_depth);
+        let depths = row.highlighting().iter().filter_map(|kind| match kind {
+            highlighting::Type::Bracket(depth) => Some(*depth),
+            _ => None,
+        }).collect();
+        (depths, end_depth)
+    }
+
+    #[test]
+    fn bracket_depth_carries_over_to_the_next_rows() {
+        assert_eq!(bracket_depths("fn f() {", 0), (vec![0, 0, 0], 1));
+        assert_eq!(bracket_depths("    g([x], \"(\");", 1), (vec![1, 2, 2, 1], 1));
+        assert_eq!(bracket_depths("}", 1), (vec![0], 0));
+    }
+
+    #[test]
+    fn unmatched_closers_stay_at_the_top() {
+        assert_eq!(bracket_depths(")] (", 0), (vec![0, 0, 0], 1));
+        assert_eq!(bracket_depths("} }", 1), (vec![0, 0], 0));
+    }
+}
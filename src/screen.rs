@@ -0,0 +1,100 @@
+use std::cmp;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use crate::theme::Style;
+
+#[derive(Clone, PartialEq)]
+pub struct Cell {
+    pub grapheme: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            grapheme: " ".to_string(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// Everything that should be on the terminal for one frame. `Terminal::draw` compares it
+/// with the previous frame and only sends what changed.
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Screen {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width.saturating_mul(height)],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn row(&self, y: usize) -> &[Cell] {
+        let start = cmp::min(y.saturating_mul(self.width), self.cells.len());
+        let end = cmp::min(start.saturating_add(self.width), self.cells.len());
+        &self.cells[start..end]
+    }
+
+    // Anything outside of the screen is dropped.
+    pub(crate) fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            if let Some(target) = self.cells.get_mut(y.saturating_mul(self.width).saturating_add(x)) {
+                *target = cell;
+            }
+        }
+    }
+
+    // Returns the column right after the text. Wide graphemes are followed by empty cells for the
+    // columns they cover, and one that doesn't fit on the screen anymore is left out.
+    pub(crate) fn put_str(&mut self, x: usize, y: usize, text: &str, style: Style) -> usize {
+        let mut x = x;
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width();
+            if width == 0 {
+                continue;
+            }
+            let next_x = x.saturating_add(width);
+            let grapheme = if next_x > self.width {
+                " ".to_string()
+            } else {
+                grapheme.to_string()
+            };
+            self.set(x, y, Cell {
+                grapheme,
+                style,
+            });
+            for column in x.saturating_add(1)..next_x {
+                self.set(column, y, Cell {
+                    grapheme: String::new(),
+                    style,
+                });
+            }
+            x = next_x;
+        }
+        x
+    }
+
+    pub(crate) fn fill(&mut self, y: usize, style: Style) {
+        for x in 0..self.width {
+            self.set(x, y, Cell {
+                grapheme: " ".to_string(),
+                style,
+            });
+        }
+    }
+}
+
@@ -0,0 +1,142 @@
+use std::cmp;
+use tree_sitter::{InputEdit, Node, Parser, Point, Tree, TreeCursor};
+use crate::highlighting;
+use crate::FileType;
+use crate::Row;
+
+// A tree-sitter parse of the whole document, kept up to date with every edit so
+// reparsing only has to look at what changed.
+pub struct Syntax {
+    parser: Parser,
+    tree: Option<Tree>,
+    stale: bool,
+}
+
+impl Syntax {
+    // None if there is no grammar for this file type, in which case the plain scanner is used.
+    pub fn new(file_type: &FileType) -> Option<Self> {
+        let grammar = file_type.grammar()?;
+        let mut parser = Parser::new();
+        parser.set_language(grammar).ok()?;
+        Some(Self {
+            parser,
+            tree: None,
+            stale: true,
+        })
+    }
+
+    pub fn edit(&mut self, edit: &InputEdit) {
+        if let Some(tree) = &mut self.tree {
+            tree.edit(edit);
+        }
+        self.stale = true;
+    }
+
+    // Reparses if anything was edited and returns the rows whose syntax changed because of it.
+    pub fn parse(&mut self, rows: &[Row]) -> Vec<(usize, usize)> {
+        if !self.stale {
+            return Vec::new();
+        }
+        self.stale = false;
+
+        let mut read = |_: usize, point: Point| -> &[u8] {
+            match rows.get(point.row) {
+                Some(row) if point.column < row.as_bytes().len() => &row.as_bytes()[point.column..],
+                Some(_) if point.row.saturating_add(1) < rows.len() => b"\n",
+                _ => &[],
+            }
+        };
+        let new_tree = self.parser.parse_with_options(&mut read, self.tree.as_ref(), None);
+
+        let changed = match (&self.tree, &new_tree) {
+            (Some(old_tree), Some(new_tree)) => old_tree
+                .changed_ranges(new_tree)
+                .map(|range| (range.start_point.row, range.end_point.row))
+                .collect(),
+            _ => vec![(0, rows.len())],
+        };
+        self.tree = new_tree;
+        changed
+    }
+
+    pub fn highlight_row(&self, index: usize, row: &Row) -> Vec<highlighting::Type> {
+        let chars = row.as_bytes().iter().filter(|byte| !is_continuation_byte(**byte)).count();
+        let mut highlighting = vec![highlighting::Type::None; chars];
+        if let Some(tree) = &self.tree {
+            let mut cursor = tree.walk();
+            paint(&mut cursor, index, row, &mut highlighting);
+        }
+        highlighting
+    }
+}
+
+// Colors everything under the cursor's node that lies on the given row.
+fn paint(cursor: &mut TreeCursor, index: usize, row: &Row, highlighting: &mut [highlighting::Type]) {
+    let node = cursor.node();
+    if let Some(hl_type) = classify(node) {
+        let start = if node.start_position().row < index {
+            0
+        } else {
+            node.start_position().column
+        };
+        let end = if node.end_position().row > index {
+            row.as_bytes().len()
+        } else {
+            node.end_position().column
+        };
+        let start = char_index(row, start);
+        let end = cmp::min(char_index(row, end), highlighting.len());
+        for hl in highlighting.iter_mut().take(end).skip(start) {
+            *hl = hl_type;
+        }
+        return;
+    }
+
+    if cursor.goto_first_child_for_point(Point::new(index, 0)).is_some() {
+        loop {
+            if cursor.node().start_position().row > index {
+                break;
+            }
+            paint(cursor, index, row, highlighting);
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+}
+
+fn classify(node: Node) -> Option<highlighting::Type> {
+    let kind = node.kind();
+    let in_macro_name = || node.parent().is_some_and(|parent| parent.kind() == "macro_invocation");
+    match kind {
+        "line_comment" => Some(highlighting::Type::Comment),
+        "block_comment" => Some(highlighting::Type::MultiLineComment),
+        "string_literal" | "raw_string_literal" => Some(highlighting::Type::String),
+        "char_literal" => Some(highlighting::Type::Character),
+        "integer_literal" | "float_literal" => Some(highlighting::Type::Number),
+        "primitive_type" | "lifetime" => Some(highlighting::Type::SecondaryKeywords),
+        "self" | "crate" | "super" | "boolean_literal" | "mutable_specifier" | "macro_rules!" => {
+            Some(highlighting::Type::PrimaryKeywords)
+        }
+        "identifier" | "scoped_identifier" | "!" if in_macro_name() => Some(highlighting::Type::PrimaryKeywords),
+        // anonymous word tokens are the grammar's keywords: fn, let, impl, where...
+        _ if !node.is_named() && !kind.is_empty() && kind.chars().all(|c| c.is_ascii_lowercase() || c == '_') => {
+            Some(highlighting::Type::PrimaryKeywords)
+        }
+        _ => None,
+    }
+}
+
+// Tree-sitter speaks in bytes while a row's highlighting has one entry per char.
+fn char_index(row: &Row, byte_index: usize) -> usize {
+    row.as_bytes()
+        .iter()
+        .take(byte_index)
+        .filter(|byte| !is_continuation_byte(**byte))
+        .count()
+}
+
+fn is_continuation_byte(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
@@ -1,86 +1,524 @@
 use crate::Position;
-use std::io::{self, stdout, Write};
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
-use termion::color;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::panic;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use signal_hook::consts::SIGWINCH;
+use termion::event::{self, Key, MouseEvent};
+use termion::input::MouseTerminal;
+use termion::screen;
+use termion::style;
+use crate::screen::Screen;
 
+// What termion's MouseTerminal sends when dropped, which a panic doesn't get to.
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+// With bracketed paste on, the terminal puts pasted text between these instead of typing it out.
+const PASTE_ON: &str = "\x1b[?2004h";
+const PASTE_OFF: &str = "\x1b[?2004l";
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+// How long the rest of an escape sequence gets to come in before the escape counts as the key itself.
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+// The terminal, opened as /dev/tty so that stdin and stdout stay free for pipes, and how it was
+// set up before raw mode. Both are kept here so a panic can put it back.
+static TTY: OnceLock<File> = OnceLock::new();
+static ORIGINAL_MODE: OnceLock<libc::termios> = OnceLock::new();
+
+#[derive(Clone, Copy)]
 pub struct Size {
     pub width: u16,
     pub height: u16
 }
 
+#[derive(PartialEq, Clone, Copy)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    Monochrome,
+}
+
+impl ColorSupport {
+    /// There is no reliable way to ask the terminal itself, so go by what the environment advertises.
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some() {
+            return Self::Monochrome;
+        }
+
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Self::TrueColor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" || term.starts_with("vt") {
+            Self::Monochrome
+        } else if term.ends_with("-direct") {
+            Self::TrueColor
+        } else if term.contains("256color") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+}
+
+pub enum Event {
+    Key(Key),
+    Mouse(MouseEvent),
+    Paste(String),
+    Resize,  // the new size is already in place
+    Wake,    // something on another thread asked for attention
+    Timeout,
+}
+
+/// Where the editor draws and gets its input from. `Terminal` is the real one, `VirtualTerminal`
+/// keeps everything in memory.
+pub trait Backend {
+    /// The size of the text area, which leaves out the status and message bars.
+    fn size(&self) -> Size;
+    fn colors(&self) -> ColorSupport;
+    /// Writing anything to this makes `read_event` return `Event::Wake`.
+    fn waker(&self) -> Result<UnixStream, std::io::Error>;
+    /// Waits for the next event, or until `timeout` runs out.
+    fn read_event(&mut self, timeout: Option<Duration>) -> Result<Event, std::io::Error>;
+    /// Puts `screen` up with the cursor at `cursor`.
+    fn draw(&mut self, screen: Screen, cursor: &Position) -> Result<(), std::io::Error>;
+}
+
 pub struct Terminal {
     size: Size,
-    _stdout: RawTerminal<std::io::Stdout>,
+    colors: ColorSupport,
+    signals: UnixStream, // read end of the pipe SIGWINCH gets written to
+    wake_reader: UnixStream,
+    wake_writer: UnixStream,
+    pending: VecDeque<Event>,
+    input: Input,
+    previous: Option<Screen>, // what was last drawn, so the next frame only has to send the difference
+    tty: File,
+    _mouse: MouseTerminal<File>,
 }
 
 impl Terminal {
     pub fn default() -> Result<Self, std::io::Error> {
-        let size = termion::terminal_size()?;
-        Ok(Self{
-            size : Size {
-                width: size.0,
-                height: size.1.saturating_sub(2), //status bar
-            },
-            _stdout: stdout().into_raw_mode()?,
-        })
+        let (signals, signal_writer) = UnixStream::pair()?;
+        signals.set_nonblocking(true)?;
+        signal_hook::low_level::pipe::register(SIGWINCH, signal_writer)?;
+        let (wake_reader, wake_writer) = UnixStream::pair()?;
+        wake_reader.set_nonblocking(true)?;
+        let tty = termion::get_tty()?;
+        let _ = TTY.set(tty.try_clone()?);
+        enter_raw_mode(&tty)?;
+        install_panic_hook();
+        let mut terminal = Self {
+            size : read_size(&tty)?,
+            colors: ColorSupport::detect(),
+            signals,
+            wake_reader,
+            wake_writer,
+            pending: VecDeque::new(),
+            input: Input::default(),
+            previous: None,
+            _mouse: MouseTerminal::from(tty.try_clone()?),
+            tty,
+        };
+        write!(terminal.tty, "{}{}", screen::ToAlternateScreen, PASTE_ON)?;
+        terminal.tty.flush()?;
+        Ok(terminal)
     }
 
-    pub fn size(&self) -> &Size {
-        &self.size
+}
+
+// What came in from the terminal that isn't events yet. Reads can end anywhere, even in the
+// middle of a paste marker, so whatever isn't finished waits for the next one.
+#[derive(Default)]
+struct Input {
+    paste: Option<Vec<u8>>, // a paste that hasn't ended yet
+    unfinished: Vec<u8>, // the start of an escape sequence or character, the rest of which is still to come
+}
+
+impl Input {
+    // Turns `input` into events, with anything between the paste markers becoming a single paste.
+    fn take(&mut self, input: Vec<u8>) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut input = [std::mem::take(&mut self.unfinished), input].concat();
+        loop {
+            if let Some(mut paste) = self.paste.take() {
+                paste.append(&mut input);
+                let Some(end) = find(&paste, PASTE_END) else {
+                    self.paste = Some(paste);
+                    return events;
+                };
+                input = paste.split_off(end).split_off(PASTE_END.len());
+                // terminals send the enter key's carriage return for line breaks
+                let text = String::from_utf8_lossy(&paste).replace("\r\n", "\n").replace('\r', "\n");
+                events.push(Event::Paste(text));
+            } else if let Some(start) = find(&input, PASTE_START) {
+                let rest = input.split_off(start).split_off(PASTE_START.len());
+                events.extend(parse_input(&input));
+                self.paste = Some(Vec::new());
+                input = rest;
+            } else {
+                // this also keeps back a paste marker that is still missing its end
+                self.unfinished = input.split_off(complete_length(&input));
+                events.extend(parse_input(&input));
+                return events;
+            }
+        }
     }
 
-    pub fn clear_screen() {
-        println!("{}", termion::clear::All);
+    fn is_unfinished(&self) -> bool {
+        !self.unfinished.is_empty()
     }
 
-    pub fn cursor_position(position: &Position) {
-        let Position{mut x, mut y} = position;
-        x = x.saturating_add(1);
-        y = y.saturating_add(1);
-        let x = x as u16;
-        let y = y as u16;
-        print!("{}", termion::cursor::Goto(x,y));
+    // For when nothing more came in for a while, so what there is has to do.
+    fn finish(&mut self) -> Vec<Event> {
+        parse_input(&std::mem::take(&mut self.unfinished))
+    }
+}
+
+impl Backend for Terminal {
+    fn size(&self) -> Size {
+        self.size
     }
 
-    pub fn flush() -> Result<(), std::io::Error> {
-        io::stdout().flush()
+    fn waker(&self) -> Result<UnixStream, std::io::Error> {
+        self.wake_writer.try_clone()
     }
 
-    pub fn read_key() -> Result<Key, std::io::Error> {
+    fn colors(&self) -> ColorSupport {
+        self.colors
+    }
+
+    // Sleeps until there is input, a resize or a wake-up, or until `timeout` runs out.
+    fn read_event(&mut self, timeout: Option<Duration>) -> Result<Event, std::io::Error> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        let mut fds = [
+            poll_fd(&self.tty),
+            poll_fd(&self.signals),
+            poll_fd(&self.wake_reader),
+        ];
+        let timeout = if self.input.is_unfinished() {
+            Some(timeout.map_or(ESCAPE_TIMEOUT, |timeout| timeout.min(ESCAPE_TIMEOUT)))
+        } else {
+            timeout
+        };
+        // rounded up, so a timer isn't polled for just before it is due
+        let timeout = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis().saturating_add(1)).unwrap_or(i32::MAX)
+        });
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            // SAFETY: `fds` is a valid array of pollfd structs and its length is passed along with it.
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+            if ready > 0 {
+                break;
+            } else if ready == 0 {
+                self.pending.extend(self.input.finish());
+                return Ok(self.pending.pop_front().unwrap_or(Event::Timeout));
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+
+        let [input, signals, wake] = fds;
+        if signals.revents != 0 {
+            drain(&mut self.signals)?;
+            self.size = read_size(&self.tty)?;
+            self.pending.push_back(Event::Resize);
+        }
+        if wake.revents != 0 {
+            drain(&mut self.wake_reader)?;
+            self.pending.push_back(Event::Wake);
+        }
+        if input.revents != 0 {
+            let mut buffer = vec![0; 4096];
+            let count = self.tty.read(&mut buffer)?;
+            if count == 0 {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "Input was closed."));
+            }
+            buffer.truncate(count);
+            let events = self.input.take(buffer);
+            self.pending.extend(events);
+        }
+
+        Ok(self.pending.pop_front().unwrap_or(Event::Timeout))
+    }
+
+    // Sends whatever differs from the previous frame, all in a single write. After a resize,
+    // or when nothing was drawn yet, the whole screen goes out.
+    fn draw(&mut self, screen: Screen, cursor: &Position) -> Result<(), std::io::Error> {
+        let previous = self.previous.take().filter(|previous| {
+            previous.width() == screen.width() && previous.height() == screen.height()
+        });
+        let mut output = termion::cursor::Hide.to_string();
+        if previous.is_none() {
+            output.push_str(style::Reset.as_ref());
+            output.push_str(termion::clear::All.as_ref());
+        }
+
+        let mut current_style = None;
+        for y in 0..screen.height() {
+            let old_row = previous.as_ref().map(|previous| previous.row(y));
+            let mut in_span = false;
+            for (x, cell) in screen.row(y).iter().enumerate() {
+                if old_row.and_then(|old_row| old_row.get(x)) == Some(cell) {
+                    in_span = false;
+                    continue;
+                }
+                if !in_span {
+                    output.push_str(&goto(x, y).to_string());
+                    in_span = true;
+                }
+                if current_style != Some(cell.style) {
+                    output.push_str(&cell.style.escape(self.colors));
+                    current_style = Some(cell.style);
+                }
+                output.push_str(&cell.grapheme);
+            }
+        }
+
+        output.push_str(style::Reset.as_ref());
+        output.push_str(&goto(cursor.x, cursor.y).to_string());
+        output.push_str(termion::cursor::Show.as_ref());
+        self.tty.write_all(output.as_bytes())?;
+        self.tty.flush()?;
+        self.previous = Some(screen);
+        Ok(())
+    }
+}
+
+// Leaving the alternate screen brings back whatever was there before the editor started.
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// termion's raw mode only works on stdout, so this is done by hand.
+fn enter_raw_mode(tty: &File) -> Result<(), std::io::Error> {
+    // SAFETY: termios is plain data, which tcgetattr fills in completely when it succeeds.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: `termios` is a valid termios struct to write into.
+    if unsafe { libc::tcgetattr(tty.as_raw_fd(), &raw mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let _ = ORIGINAL_MODE.set(termios);
+    // SAFETY: `termios` holds the settings tcgetattr just handed out.
+    unsafe { libc::cfmakeraw(&raw mut termios) };
+    // SAFETY: the same settings, changed by cfmakeraw only.
+    if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &raw const termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // a background job panicking doesn't bring the editor down, so the terminal stays as it is
+        if thread::current().name() == Some("main") {
+            restore();
+        }
+        default_hook(info);
+    }));
+}
+
+fn restore() {
+    let Some(mut tty) = TTY.get() else {
+        return;
+    };
+    if let Some(termios) = ORIGINAL_MODE.get() {
+        // SAFETY: these are the settings tcgetattr handed out earlier.
+        unsafe {
+            libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, termios);
+        }
+    }
+    let _ = write!(tty, "{}{}{}{}{}", PASTE_OFF, MOUSE_OFF, style::Reset, termion::cursor::Show, screen::ToMainScreen);
+    let _ = tty.flush();
+}
+
+// termion only asks stdout for its size, which may be a pipe.
+fn read_size(tty: &File) -> Result<Size, std::io::Error> {
+    // SAFETY: winsize is plain data, which the ioctl fills in completely when it succeeds.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ writes a winsize struct, and `size` is a valid one.
+    if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &raw mut size) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Size {
+        width: size.ws_col,
+        height: size.ws_row.saturating_sub(2), //status bar
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn goto(x: usize, y: usize) -> termion::cursor::Goto {
+    termion::cursor::Goto(x.saturating_add(1) as u16, y.saturating_add(1) as u16)
+}
+
+fn poll_fd(fd: &impl AsRawFd) -> libc::pollfd {
+    libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }
+}
+
+fn drain(stream: &mut UnixStream) -> Result<(), std::io::Error> {
+    let mut buffer = [0; 64];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn parse_input(buffer: &[u8]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut bytes = buffer.iter().copied().map(Ok);
+    while let Some(Ok(byte)) = bytes.next() {
+        // the rest of a sequence would have been waited for, so an escape with nothing after it is the key itself
+        if byte == b'\x1B' && bytes.len() == 0 {
+            events.push(Event::Key(Key::Esc));
+        } else {
+            match event::parse_event(byte, &mut bytes) {
+                Ok(event::Event::Key(key)) => events.push(Event::Key(key)),
+                Ok(event::Event::Mouse(mouse)) => events.push(Event::Mouse(mouse)),
+                _ => (),
             }
         }
     }
+    events
+}
+
+// How much of `input` can be parsed now. An escape sequence or a UTF-8 character cut off at the
+// end is left out, for the next read to finish.
+fn complete_length(input: &[u8]) -> usize {
+    if let Some(start) = input.iter().rposition(|byte| *byte == b'\x1B') {
+        if !escape_finished(input.get(start.saturating_add(1)..).unwrap_or_default()) {
+            return start;
+        }
+    }
+    let Some(start) = input.iter().rposition(|byte| byte & 0xC0 != 0x80) else {
+        return input.len();
+    };
+    if char_finished(input.get(start..).unwrap_or_default()) {
+        input.len()
+    } else {
+        start
+    }
+}
 
-    pub fn cursor_hide() {
-        print!("{}", termion::cursor::Hide);
+// Whether what follows an escape is all there: a CSI sequence up to its final byte, the key after
+// an SS3, the three bytes of an X10 mouse report, or the character typed with Alt.
+fn escape_finished(rest: &[u8]) -> bool {
+    match rest {
+        [] | [b'O'] => false,
+        [b'[', b'M', report @ ..] => report.len() >= 3,
+        [b'[', sequence @ ..] => sequence.iter().any(|byte| (0x40..=0x7E).contains(byte)),
+        _ => char_finished(rest),
     }
+}
 
-    pub fn cursor_show() {
-        print!("{}", termion::cursor::Show);
+// Whether `bytes` holds all of the UTF-8 character it starts with.
+fn char_finished(bytes: &[u8]) -> bool {
+    let Some(first) = bytes.first() else {
+        return false;
+    };
+    let length = match first.leading_ones() {
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        _ => 1,
+    };
+    bytes.len() >= length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_off_sequences_wait_for_the_rest() {
+        assert_eq!(complete_length(b"abc"), 3);
+        assert_eq!(complete_length(b"a\x1b"), 1);
+        assert_eq!(complete_length(b"a\x1b["), 1);
+        assert_eq!(complete_length(b"a\x1b[1;5"), 1);
+        assert_eq!(complete_length(b"a\x1b[1;5A"), 7);
+        assert_eq!(complete_length(b"\x1bO"), 0);
+        assert_eq!(complete_length(b"\x1bOP"), 3);
+        assert_eq!(complete_length(b"\x1b[<0;10;5"), 0);
+        assert_eq!(complete_length(b"\x1b[<0;10;5M"), 10);
+        assert_eq!(complete_length(b"\x1b[M ab"), 6);
+        assert_eq!(complete_length(b"\x1b[M a"), 0);
+        assert_eq!(complete_length(b"x\x1b[20"), 1);
+        assert_eq!(complete_length(b"\x1bx"), 2);
     }
 
-    pub fn clear_current_line() {
-        print!("{}", termion::clear::CurrentLine);
+    #[test]
+    fn cut_off_characters_wait_for_the_rest() {
+        let text = "a\u{e9}\u{20ac}";
+        let bytes = text.as_bytes();
+        assert_eq!(complete_length(bytes), bytes.len());
+        assert_eq!(complete_length(&bytes[..2]), 1);
+        assert_eq!(complete_length(&bytes[..4]), 3);
+        assert_eq!(complete_length(&bytes[..5]), 3);
+        assert_eq!(complete_length(b"\x1b\xc3"), 0);
+        assert_eq!(complete_length(b"\x1b\xc3\xa9"), 3);
     }
 
-    pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+    fn paste(events: &[Event]) -> Option<&str> {
+        match events {
+            [Event::Paste(text)] => Some(text),
+            _ => None,
+        }
     }
 
-    pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    #[test]
+    fn a_paste_becomes_one_event() {
+        let mut input = Input::default();
+        let events = input.take(b"a\x1b[200~one\rtwo\x1b[201~b".to_vec());
+        assert!(matches!(events.as_slice(), [Event::Key(Key::Char('a')), Event::Paste(text), Event::Key(Key::Char('b'))] if text == "one\ntwo"));
     }
 
-    pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+    #[test]
+    fn paste_markers_can_be_split_over_reads() {
+        let mut input = Input::default();
+        assert!(input.take(b"\x1b[20".to_vec()).is_empty());
+        assert!(input.take(b"0~ab\x1b[2".to_vec()).is_empty());
+        assert_eq!(paste(&input.take(b"01~".to_vec())), Some("ab"));
+
+        assert!(input.take(b"\x1b".to_vec()).is_empty());
+        assert!(input.take(b"[200".to_vec()).is_empty());
+        assert!(input.take(b"~x\x1b".to_vec()).is_empty());
+        assert_eq!(paste(&input.take(b"[201~".to_vec())), Some("x"));
+        assert!(!input.is_unfinished());
     }
 
-    pub fn reset_fg_color() {
-        print!("{}", color::Bg(color::Reset));
+    #[test]
+    fn a_lone_escape_is_the_key() {
+        let mut input = Input::default();
+        assert!(input.take(b"\x1b".to_vec()).is_empty());
+        assert!(matches!(input.finish().as_slice(), [Event::Key(Key::Esc)]));
+        assert!(matches!(parse_input(b"\x1b[A").as_slice(), [Event::Key(Key::Up)]));
     }
-}
\ No newline at end of file
+}
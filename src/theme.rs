@@ -0,0 +1,414 @@
+use termion::{color, style};
+use crate::highlighting;
+use crate::terminal::ColorSupport;
+
+// xterm's values for the 16 basic colors, which is what most terminals ship with
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Default, Clone, Copy, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    fg: Option<color::Rgb>,
+    bg: Option<color::Rgb>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+}
+
+impl Style {
+    const fn plain() -> Self {
+        Self {
+            fg: None,
+            bg: None,
+            bold: false,
+            italic: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    const fn fg(r: u8, g: u8, b: u8) -> Self {
+        let mut style = Self::plain();
+        style.fg = Some(color::Rgb(r, g, b));
+        style
+    }
+
+    const fn on(mut self, r: u8, g: u8, b: u8) -> Self {
+        self.bg = Some(color::Rgb(r, g, b));
+        self
+    }
+
+    const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    const fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    const fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    const fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// Always starts from a reset so attributes of the previous style don't leak into this one.
+    /// Without colors, anything that relies on a background to stand out is shown in reverse video.
+    pub fn escape(&self, colors: ColorSupport) -> String {
+        let mut result = style::Reset.to_string();
+        if colors == ColorSupport::Monochrome {
+            if self.reverse || self.bg.is_some() {
+                result.push_str(style::Invert.as_ref());
+            }
+        } else {
+            if let Some(fg) = self.fg {
+                result.push_str(&color_escape(fg, colors, false));
+            }
+            if let Some(bg) = self.bg {
+                result.push_str(&color_escape(bg, colors, true));
+            }
+            if self.reverse {
+                result.push_str(style::Invert.as_ref());
+            }
+        }
+        if self.bold {
+            result.push_str(style::Bold.as_ref());
+        }
+        if self.italic {
+            result.push_str(style::Italic.as_ref());
+        }
+        if self.underline {
+            result.push_str(style::Underline.as_ref());
+        }
+        result
+    }
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    name: &'static str,
+    text: Style,
+    number: Style,
+    search_match: Style,
+    string: Style,
+    character: Style,
+    comment: Style,
+    multiline_comment: Style,
+    primary_keywords: Style,
+    secondary_keywords: Style,
+    status_bar: Style,
+    message_bar: Style,
+    gutter: Style,
+    selection: Style,
+    matching_bracket: Style,
+    rainbow: Vec<Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::hecto()
+    }
+}
+
+impl Theme {
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::hecto(), Self::solarized_dark(), Self::gruvbox_dark(), Self::monokai(), Self::monochrome()]
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::builtin().into_iter().find(|theme| theme.name == name)
+    }
+
+    pub fn next(&self) -> Self {
+        let themes = Self::builtin();
+        let index = themes.iter().position(|theme| theme.name == self.name).unwrap_or(0);
+        themes.into_iter().cycle().nth(index.saturating_add(1)).unwrap_or_default()
+    }
+
+    fn hecto() -> Self {
+        Self {
+            name: "hecto",
+            text: Style::fg(255, 255, 255),
+            number: Style::fg(220, 163, 163),
+            search_match: Style::fg(38, 139, 210),
+            string: Style::fg(211, 54, 130),
+            character: Style::fg(108, 113, 196),
+            comment: Style::fg(133, 153, 0),
+            multiline_comment: Style::fg(133, 153, 0),
+            primary_keywords: Style::fg(181, 137, 0),
+            secondary_keywords: Style::fg(42, 161, 152),
+            status_bar: Style::fg(63, 63, 63).on(239, 239, 239),
+            message_bar: Style::plain(),
+            gutter: Style::fg(110, 110, 110),
+            selection: Style::fg(255, 255, 255).on(68, 68, 68),
+            matching_bracket: Style::fg(255, 255, 255).on(38, 139, 210).bold(),
+            rainbow: vec![Style::fg(255, 215, 0), Style::fg(218, 112, 214), Style::fg(23, 159, 255)],
+        }
+    }
+
+    fn solarized_dark() -> Self {
+        Self {
+            name: "solarized-dark",
+            text: Style::fg(131, 148, 150),
+            number: Style::fg(211, 54, 130),
+            search_match: Style::fg(0, 43, 54).on(181, 137, 0),
+            string: Style::fg(42, 161, 152),
+            character: Style::fg(108, 113, 196),
+            comment: Style::fg(88, 110, 117).italic(),
+            multiline_comment: Style::fg(88, 110, 117).italic(),
+            primary_keywords: Style::fg(133, 153, 0).bold(),
+            secondary_keywords: Style::fg(181, 137, 0),
+            status_bar: Style::fg(147, 161, 161).on(7, 54, 66),
+            message_bar: Style::fg(131, 148, 150),
+            gutter: Style::fg(88, 110, 117),
+            selection: Style::fg(147, 161, 161).on(7, 54, 66),
+            matching_bracket: Style::fg(253, 246, 227).on(88, 110, 117).bold(),
+            rainbow: vec![
+                Style::fg(181, 137, 0),
+                Style::fg(211, 54, 130),
+                Style::fg(38, 139, 210),
+                Style::fg(42, 161, 152),
+                Style::fg(133, 153, 0),
+                Style::fg(203, 75, 22),
+            ],
+        }
+    }
+
+    fn gruvbox_dark() -> Self {
+        Self {
+            name: "gruvbox-dark",
+            text: Style::fg(235, 219, 178),
+            number: Style::fg(211, 134, 155),
+            search_match: Style::fg(40, 40, 40).on(250, 189, 47),
+            string: Style::fg(184, 187, 38),
+            character: Style::fg(211, 134, 155),
+            comment: Style::fg(146, 131, 116).italic(),
+            multiline_comment: Style::fg(146, 131, 116).italic(),
+            primary_keywords: Style::fg(251, 73, 52),
+            secondary_keywords: Style::fg(250, 189, 47),
+            status_bar: Style::fg(235, 219, 178).on(80, 73, 69),
+            message_bar: Style::fg(235, 219, 178),
+            gutter: Style::fg(124, 111, 100),
+            selection: Style::fg(235, 219, 178).on(80, 73, 69),
+            matching_bracket: Style::fg(251, 241, 199).on(102, 92, 84).bold(),
+            rainbow: vec![
+                Style::fg(250, 189, 47),
+                Style::fg(211, 134, 155),
+                Style::fg(131, 165, 152),
+                Style::fg(142, 192, 124),
+                Style::fg(254, 128, 25),
+            ],
+        }
+    }
+
+    fn monokai() -> Self {
+        Self {
+            name: "monokai",
+            text: Style::fg(248, 248, 242),
+            number: Style::fg(174, 129, 255),
+            search_match: Style::fg(39, 40, 34).on(230, 219, 116).underline(),
+            string: Style::fg(230, 219, 116),
+            character: Style::fg(174, 129, 255),
+            comment: Style::fg(117, 113, 94),
+            multiline_comment: Style::fg(117, 113, 94),
+            primary_keywords: Style::fg(249, 38, 114),
+            secondary_keywords: Style::fg(102, 217, 239).italic(),
+            status_bar: Style::fg(248, 248, 242).on(73, 72, 62),
+            message_bar: Style::fg(248, 248, 242),
+            gutter: Style::fg(144, 144, 138),
+            selection: Style::fg(248, 248, 242).on(73, 72, 62),
+            matching_bracket: Style::fg(248, 248, 242).on(117, 113, 94).bold(),
+            rainbow: vec![
+                Style::fg(230, 219, 116),
+                Style::fg(174, 129, 255),
+                Style::fg(102, 217, 239),
+                Style::fg(166, 226, 46),
+                Style::fg(253, 151, 31),
+            ],
+        }
+    }
+
+    // Meant for terminals without colors, so it only relies on attributes.
+    fn monochrome() -> Self {
+        Self {
+            name: "monochrome",
+            text: Style::plain(),
+            number: Style::plain(),
+            search_match: Style::plain().reverse(),
+            string: Style::plain().underline(),
+            character: Style::plain().underline(),
+            comment: Style::plain().italic(),
+            multiline_comment: Style::plain().italic(),
+            primary_keywords: Style::plain().bold(),
+            secondary_keywords: Style::plain().bold(),
+            status_bar: Style::plain().reverse(),
+            message_bar: Style::plain(),
+            gutter: Style::plain(),
+            selection: Style::plain().reverse(),
+            matching_bracket: Style::plain().bold().underline(),
+            rainbow: vec![Style::plain(), Style::plain().bold(), Style::plain().underline()],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn highlight(&self, hl_type: highlighting::Type) -> &Style {
+        match hl_type {
+            highlighting::Type::None => &self.text,
+            highlighting::Type::Number => &self.number,
+            highlighting::Type::Match => &self.search_match,
+            highlighting::Type::String => &self.string,
+            highlighting::Type::Character => &self.character,
+            highlighting::Type::Comment => &self.comment,
+            highlighting::Type::MultiLineComment => &self.multiline_comment,
+            highlighting::Type::PrimaryKeywords => &self.primary_keywords,
+            highlighting::Type::SecondaryKeywords => &self.secondary_keywords,
+            highlighting::Type::Bracket(depth) => depth
+                .checked_rem(self.rainbow.len())
+                .and_then(|index| self.rainbow.get(index))
+                .unwrap_or(&self.text),
+        }
+    }
+
+    pub fn status_bar(&self) -> &Style {
+        &self.status_bar
+    }
+
+    pub fn message_bar(&self) -> &Style {
+        &self.message_bar
+    }
+
+    pub fn gutter(&self) -> &Style {
+        &self.gutter
+    }
+
+    pub fn selection(&self) -> &Style {
+        &self.selection
+    }
+
+    pub fn matching_bracket(&self) -> &Style {
+        &self.matching_bracket
+    }
+}
+
+fn color_escape(rgb: color::Rgb, colors: ColorSupport, background: bool) -> String {
+    match colors {
+        ColorSupport::TrueColor if background => color::Bg(rgb).to_string(),
+        ColorSupport::TrueColor => color::Fg(rgb).to_string(),
+        ColorSupport::Ansi256 if background => color::Bg(color::AnsiValue(ansi_256(rgb))).to_string(),
+        ColorSupport::Ansi256 => color::Fg(color::AnsiValue(ansi_256(rgb))).to_string(),
+        ColorSupport::Ansi16 => {
+            // the plain 30-37/90-97 codes, since the Linux console doesn't know the 38;5 form
+            let index = ansi_16(rgb);
+            let base = match (background, index < 8) {
+                (false, true) => 30,
+                (false, false) => 82,
+                (true, true) => 40,
+                (true, false) => 92,
+            };
+            format!("\x1b[{}m", base + index)
+        },
+        ColorSupport::Monochrome => String::new(),
+    }
+}
+
+fn distance(rgb: color::Rgb, (r, g, b): (u8, u8, u8)) -> u32 {
+    let channel = |from: u8, to: u8| u32::from(from.abs_diff(to)).pow(2);
+    channel(rgb.0, r) + channel(rgb.1, g) + channel(rgb.2, b)
+}
+
+fn ansi_16(rgb: color::Rgb) -> u8 {
+    let mut best = 0;
+    for (index, candidate) in (0..).zip(ANSI_16) {
+        if distance(rgb, candidate) < distance(rgb, ANSI_16[usize::from(best)]) {
+            best = index;
+        }
+    }
+    best
+}
+
+// Picks whichever is closer: the nearest entry of the 6x6x6 color cube or of the grayscale ramp.
+fn ansi_256(rgb: color::Rgb) -> u8 {
+    let level = |channel: u8| -> u8 {
+        match channel {
+            0..=47 => 0,
+            48..=114 => 1,
+            _ => (channel - 35) / 40,
+        }
+    };
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (CUBE_LEVELS[usize::from(r)], CUBE_LEVELS[usize::from(g)], CUBE_LEVELS[usize::from(b)]);
+
+    let average = (u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3;
+    let gray_index = u8::try_from(average.saturating_sub(3) / 10).unwrap_or(23).min(23);
+    let gray_level = 8 + 10 * gray_index;
+    let gray = (gray_level, gray_level, gray_level);
+
+    if distance(rgb, gray) < distance(rgb, cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * r + 6 * g + b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_color_cube() {
+        assert_eq!(ansi_256(color::Rgb(255, 0, 0)), 196);
+        assert_eq!(ansi_256(color::Rgb(0, 255, 0)), 46);
+        assert_eq!(ansi_256(color::Rgb(0, 0, 255)), 21);
+        assert_eq!(ansi_256(color::Rgb(0, 0, 0)), 16);
+        assert_eq!(ansi_256(color::Rgb(255, 255, 255)), 231);
+        assert_eq!(ansi_256(color::Rgb(215, 135, 95)), 16 + 36 * 4 + 6 * 2 + 1);
+    }
+
+    #[test]
+    fn grays_go_to_the_gray_ramp() {
+        assert_eq!(ansi_256(color::Rgb(128, 128, 128)), 244);
+        assert_eq!(ansi_256(color::Rgb(40, 40, 40)), 235);
+        assert_eq!(ansi_256(color::Rgb(238, 238, 238)), 255);
+    }
+
+    #[test]
+    fn the_sixteen_colors() {
+        assert_eq!(ansi_16(color::Rgb(0, 0, 0)), 0);
+        assert_eq!(ansi_16(color::Rgb(200, 10, 10)), 1);
+        assert_eq!(ansi_16(color::Rgb(255, 0, 0)), 9);
+        assert_eq!(ansi_16(color::Rgb(0, 255, 0)), 10);
+        assert_eq!(ansi_16(color::Rgb(0, 0, 255)), 4);
+        assert_eq!(ansi_16(color::Rgb(255, 255, 255)), 15);
+    }
+
+    #[test]
+    fn escapes() {
+        let red = color::Rgb(255, 0, 0);
+        assert_eq!(color_escape(color::Rgb(1, 2, 3), ColorSupport::TrueColor, false), "\x1b[38;2;1;2;3m");
+        assert_eq!(color_escape(red, ColorSupport::Ansi256, false), "\x1b[38;5;196m");
+        assert_eq!(color_escape(red, ColorSupport::Ansi256, true), "\x1b[48;5;196m");
+        // bright colors take the 90-97 and 100-107 codes
+        assert_eq!(color_escape(red, ColorSupport::Ansi16, false), "\x1b[91m");
+        assert_eq!(color_escape(red, ColorSupport::Ansi16, true), "\x1b[101m");
+        assert_eq!(color_escape(color::Rgb(255, 255, 255), ColorSupport::Ansi16, false), "\x1b[97m");
+        assert_eq!(color_escape(color::Rgb(200, 10, 10), ColorSupport::Ansi16, false), "\x1b[31m");
+        assert_eq!(color_escape(color::Rgb(0, 0, 0), ColorSupport::Ansi16, true), "\x1b[40m");
+        assert_eq!(color_escape(red, ColorSupport::Monochrome, false), "");
+    }
+}
@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::time::Duration;
+use termion::event::Key;
+use crate::Position;
+use crate::screen::{Cell, Screen};
+use crate::terminal::{Backend, ColorSupport, Event, Size};
+
+/// A terminal that only exists in memory. Events are queued up front and whatever gets drawn is
+/// kept around to look at, so the editor can be driven without a TTY. Clones share everything,
+/// so one can go to the editor and another one kept to feed it and check on it.
+#[derive(Clone)]
+pub struct VirtualTerminal {
+    state: Rc<RefCell<State>>,
+    wake_reader: Rc<UnixStream>,
+    wake_writer: Rc<UnixStream>,
+}
+
+struct State {
+    size: Size,
+    events: VecDeque<Event>,
+    screen: Option<Screen>,
+    cursor: Position,
+}
+
+impl VirtualTerminal {
+    /// `width` and `height` are for the whole screen, including the status and message bars.
+    pub fn new(width: u16, height: u16) -> Result<Self, std::io::Error> {
+        let (wake_reader, wake_writer) = UnixStream::pair()?;
+        wake_reader.set_nonblocking(true)?;
+        Ok(Self {
+            state: Rc::new(RefCell::new(State {
+                size: Size {
+                    width,
+                    height: height.saturating_sub(2),
+                },
+                events: VecDeque::new(),
+                screen: None,
+                cursor: Position::default(),
+            })),
+            wake_reader: Rc::new(wake_reader),
+            wake_writer: Rc::new(wake_writer),
+        })
+    }
+
+    pub fn push_event(&self, event: Event) {
+        self.state.borrow_mut().events.push_back(event);
+    }
+
+    pub fn push_keys<I: IntoIterator<Item = Key>>(&self, keys: I) {
+        self.state.borrow_mut().events.extend(keys.into_iter().map(Event::Key));
+    }
+
+    pub fn resize(&self, width: u16, height: u16) {
+        let mut state = self.state.borrow_mut();
+        state.size = Size {
+            width,
+            height: height.saturating_sub(2),
+        };
+        state.events.push_back(Event::Resize);
+    }
+
+    /// What was drawn last, one string per screen line.
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        let Some(screen) = &state.screen else {
+            return Vec::new();
+        };
+        (0..screen.height())
+            .map(|y| screen.row(y).iter().map(|cell| cell.grapheme.as_str()).collect())
+            .collect()
+    }
+
+    /// What was drawn last at column `x` of screen line `y`.
+    pub fn cell(&self, x: usize, y: usize) -> Option<Cell> {
+        let state = self.state.borrow();
+        state.screen.as_ref()?.row(y).get(x).cloned()
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.state.borrow().cursor.clone()
+    }
+}
+
+impl Backend for VirtualTerminal {
+    fn size(&self) -> Size {
+        self.state.borrow().size
+    }
+
+    fn colors(&self) -> ColorSupport {
+        ColorSupport::TrueColor
+    }
+
+    fn waker(&self) -> Result<UnixStream, std::io::Error> {
+        self.wake_writer.try_clone()
+    }
+
+    // Time doesn't pass in here, so once the queued events are used up the input counts as closed,
+    // which ends `Editor::run`.
+    fn read_event(&mut self, _timeout: Option<Duration>) -> Result<Event, std::io::Error> {
+        let mut buffer = [0; 64];
+        match (&*self.wake_reader).read(&mut buffer) {
+            Ok(read) if read > 0 => return Ok(Event::Wake),
+            Err(error) if error.kind() != ErrorKind::WouldBlock => return Err(error),
+            _ => (),
+        }
+        self.state
+            .borrow_mut()
+            .events
+            .pop_front()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::UnexpectedEof, "Input was closed."))
+    }
+
+    fn draw(&mut self, screen: Screen, cursor: &Position) -> Result<(), std::io::Error> {
+        let mut state = self.state.borrow_mut();
+        state.screen = Some(screen);
+        state.cursor = cursor.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use hecto::{Config, Document, Editor, Event, Theme, VirtualTerminal};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process;
+use termion::event::{Key, MouseButton, MouseEvent};
+
+fn editor(width: u16, height: u16, file_name: Option<&str>) -> (Editor, VirtualTerminal) {
+    let terminal = VirtualTerminal::new(width, height).unwrap();
+    let editor = Editor::with_terminal(Box::new(terminal.clone()), file_name);
+    (editor, terminal)
+}
+
+fn type_text(terminal: &VirtualTerminal, text: &str) {
+    terminal.push_keys(text.chars().map(Key::Char));
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("hecto-test-{}-{name}", process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn running_out_of_input_ends_the_editor() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    type_text(&terminal, "hi");
+    let error = editor.run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(terminal.lines()[0].trim_end(), "hi");
+}
+
+#[test]
+fn quitting_ends_the_editor() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    terminal.push_keys([Key::Ctrl('q')]);
+    assert!(editor.run().is_ok());
+}
+
+#[test]
+fn typed_text_shows_up_with_the_cursor_after_it() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    type_text(&terminal, "fn main() {\n}");
+    terminal.push_keys([Key::Up, Key::End]);
+    let _ = editor.run();
+
+    let lines = terminal.lines();
+    assert_eq!(lines.len(), 10);
+    assert_eq!(lines[0].trim_end(), "fn main() {");
+    assert_eq!(lines[1].trim_end(), "}");
+    assert_eq!(lines[2].trim_end(), "~");
+    assert!(lines[8].starts_with("[No file name] - 2 lines (modified)"));
+    let cursor = terminal.cursor();
+    assert_eq!((cursor.x, cursor.y), (11, 0));
+}
+
+#[test]
+fn an_opened_file_is_shown_and_saved() {
+    let path = temp_file("saved.txt", "one\ntwo\n");
+    let (mut editor, terminal) = editor(40, 10, path.to_str());
+    terminal.push_keys([Key::Down, Key::End]);
+    type_text(&terminal, "!");
+    terminal.push_keys([Key::Ctrl('s'), Key::Ctrl('q')]);
+    assert!(editor.run().is_ok());
+
+    let lines = terminal.lines();
+    assert_eq!(lines[1].trim_end(), "two!");
+    assert_eq!(lines[9].trim_end(), "File saved successfully!");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo!\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn quitting_with_unsaved_changes_needs_confirming() {
+    let (mut editor, terminal) = editor(60, 10, None);
+    type_text(&terminal, "x");
+    terminal.push_keys([Key::Ctrl('q')]);
+    let _ = editor.run();
+    assert!(terminal.lines()[9].starts_with("Warning! Quitting without saving. Press Ctrl-Q 3 more times"));
+
+    terminal.push_keys([Key::Ctrl('q'); 4]);
+    assert!(editor.run().is_ok());
+}
+
+#[test]
+fn dragging_the_mouse_selects_text() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    type_text(&terminal, "hello world");
+    // termion counts columns and lines from 1
+    terminal.push_event(Event::Mouse(MouseEvent::Press(MouseButton::Left, 1, 1)));
+    terminal.push_event(Event::Mouse(MouseEvent::Hold(6, 1)));
+    let _ = editor.run();
+
+    let selection = *Theme::default().selection();
+    for x in 0..5 {
+        assert!(terminal.cell(x, 0).unwrap().style == selection, "column {x}");
+    }
+    assert!(terminal.cell(5, 0).unwrap().style != selection);
+    let cursor = terminal.cursor();
+    assert_eq!((cursor.x, cursor.y), (5, 0));
+}
+
+#[test]
+fn scrolling_after_a_click_selects_nothing() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    type_text(&terminal, &"line\n".repeat(30));
+    terminal.push_keys([Key::Ctrl('g')]);
+    type_text(&terminal, "1\n");
+    terminal.push_event(Event::Mouse(MouseEvent::Press(MouseButton::Left, 3, 1)));
+    terminal.push_event(Event::Mouse(MouseEvent::Release(3, 1)));
+    terminal.push_event(Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 3, 1)));
+    terminal.push_event(Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 3, 1)));
+    let _ = editor.run();
+
+    let selection = *Theme::default().selection();
+    for y in 0..8 {
+        assert!((0..40).all(|x| terminal.cell(x, y).unwrap().style != selection), "line {y}");
+    }
+    assert_eq!(terminal.cursor().y, 0);
+}
+
+#[test]
+fn a_resize_redraws_at_the_new_size() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    type_text(&terminal, "text");
+    terminal.resize(20, 6);
+    let _ = editor.run();
+
+    let lines = terminal.lines();
+    assert_eq!(lines.len(), 6);
+    assert!(lines.iter().all(|line| line.chars().count() == 20));
+    assert_eq!(lines[0].trim_end(), "text");
+}
+
+#[test]
+fn a_paste_goes_in_as_it_is() {
+    let (mut editor, terminal) = editor(40, 10, None);
+    terminal.push_event(Event::Paste("if a {\n\tb\n}".to_string()));
+    let _ = editor.run();
+
+    let lines = terminal.lines();
+    assert_eq!(lines[0].trim_end(), "if a {");
+    assert_eq!(lines[2].trim_end(), "}");
+    let cursor = terminal.cursor();
+    assert_eq!((cursor.x, cursor.y), (1, 2));
+}
+
+#[test]
+fn searching_matches_as_you_type_and_steps_with_the_arrows() {
+    let search = |keys: &[Key]| {
+        let (mut editor, terminal) = editor(40, 10, None);
+        type_text(&terminal, "foo fob foo");
+        terminal.push_keys([Key::Home, Key::Ctrl('f')]);
+        terminal.push_keys(keys.iter().copied());
+        terminal.push_keys([Key::Char('\n')]);
+        let _ = editor.run();
+        terminal.cursor().x
+    };
+    assert_eq!(search(&[Key::Char('f'), Key::Char('o')]), 0);
+    assert_eq!(search(&[Key::Char('f'), Key::Char('o'), Key::Right]), 4);
+    assert_eq!(search(&[Key::Char('f'), Key::Char('o'), Key::Char('o'), Key::Right]), 8);
+    assert_eq!(search(&[Key::Char('f'), Key::Char('o'), Key::Char('o'), Key::Right, Key::Left]), 0);
+}
+
+#[test]
+fn a_filter_keeps_what_was_saved_for_its_output() {
+    let terminal = VirtualTerminal::new(40, 10).unwrap();
+    let mut editor = Editor::with_document(Box::new(terminal.clone()), Document::from("piped\n"), Config::default());
+    assert!(editor.is_filter());
+    terminal.push_keys([Key::End]);
+    type_text(&terminal, " in");
+    terminal.push_keys([Key::Ctrl('s'), Key::Ctrl('q')]);
+    assert!(editor.run().is_ok());
+
+    assert!(terminal.lines()[8].starts_with("[stdin]"));
+    assert_eq!(editor.into_output().unwrap(), b"piped in\n");
+}
+
+#[test]
+fn saving_as_another_file_type_takes_on_its_settings() {
+    let path = env::temp_dir().join(format!("hecto-test-{}-save-as.rs", process::id()));
+    let terminal = VirtualTerminal::new(60, 10).unwrap();
+    let config = Config::parse("[filetype.rust]\nline_numbers = true\n");
+    let mut editor = Editor::with_config(Box::new(terminal.clone()), None, config);
+    type_text(&terminal, "fn main() {}");
+    terminal.push_keys([Key::Ctrl('s')]);
+    type_text(&terminal, path.to_str().unwrap());
+    terminal.push_keys([Key::Char('\n')]);
+    let _ = editor.run();
+
+    assert!(terminal.lines()[0].trim_start().starts_with("1 fn main() {}"), "{}", terminal.lines()[0]);
+    fs::remove_file(path).unwrap();
+}
+
+fn editor_with(config: &str) -> (Editor, VirtualTerminal) {
+    let terminal = VirtualTerminal::new(60, 10).unwrap();
+    let editor = Editor::with_config(Box::new(terminal.clone()), None, Config::parse(config));
+    (editor, terminal)
+}
+
+#[test]
+fn any_other_key_calls_off_quitting() {
+    let (mut editor, terminal) = editor(60, 10, None);
+    type_text(&terminal, "x");
+    terminal.push_keys([Key::Ctrl('q'), Key::Ctrl('q'), Key::F(5), Key::Ctrl('q')]);
+    let _ = editor.run();
+    assert!(terminal.lines()[9].starts_with("Warning! Quitting without saving. Press Ctrl-Q 3 more times"));
+}
+
+#[test]
+fn an_unbound_sequence_that_starts_with_a_character_is_typed() {
+    let (mut editor, terminal) = editor_with("[keys]\n\"space l\" = \"line-numbers\"\n");
+    type_text(&terminal, "a xy");
+    let _ = editor.run();
+    assert_eq!(terminal.lines()[0].trim_end(), "a xy");
+
+    type_text(&terminal, "  l");
+    let _ = editor.run();
+    assert_eq!(terminal.lines()[0].trim_end(), "1 a xy");
+}
+
+#[test]
+fn a_click_ends_a_key_sequence() {
+    let (mut editor, terminal) = editor(60, 10, None);
+    type_text(&terminal, "text");
+    terminal.push_keys([Key::Ctrl('k')]);
+    terminal.push_event(Event::Mouse(MouseEvent::Press(MouseButton::Left, 1, 1)));
+    terminal.push_event(Event::Mouse(MouseEvent::Release(1, 1)));
+    terminal.push_keys([Key::Ctrl('n')]);
+    let _ = editor.run();
+    assert_eq!(terminal.lines()[0].trim_end(), "text");
+    assert!(terminal.lines()[9].trim_end().is_empty(), "{}", terminal.lines()[9]);
+}
+
+#[test]
+fn a_paste_types_the_character_it_interrupts() {
+    let (mut editor, terminal) = editor_with("[keys]\n\"space l\" = \"line-numbers\"\n");
+    type_text(&terminal, "a ");
+    terminal.push_event(Event::Paste("b".to_string()));
+    let _ = editor.run();
+    assert_eq!(terminal.lines()[0].trim_end(), "a b");
+}